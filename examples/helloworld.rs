@@ -23,5 +23,5 @@ fn main() {
     r.get("/hello/:world", hello_world);
 
     let s = Server::new(addr, r);
-    s.start_http();
+    s.start_http().unwrap();
 }
\ No newline at end of file
@@ -29,5 +29,5 @@ fn main() {
 
     let der = include_bytes!("certificate.p12");
     let cert = Pkcs12::from_der(der, "password").unwrap();
-    s.start_https(cert);
+    s.start_https(cert).unwrap();
 }
\ No newline at end of file
@@ -38,5 +38,5 @@ fn main() {
     r.get("/", get_json);
 
     let s = Server::new(addr, r);
-    s.start_http();
+    s.start_http().unwrap();
 }
\ No newline at end of file
@@ -32,5 +32,5 @@ fn main() {
 
 
     let s = Server::new(addr,r);
-    s.start_http();
+    s.start_http().unwrap();
 }
\ No newline at end of file
@@ -29,5 +29,5 @@ fn main() {
     r.get("/say/*text", say);
 
     let s = Server::new(addr, r);
-    s.start_http();
+    s.start_http().unwrap();
 }
\ No newline at end of file
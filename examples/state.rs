@@ -54,5 +54,5 @@ fn main() {
 
     let s = Server::new(addr, r);
     s.add_state(state);
-    s.start_http();
+    s.start_http().unwrap();
 }
\ No newline at end of file
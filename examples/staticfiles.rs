@@ -21,5 +21,5 @@ fn main() {
     r.static_path_cached("/style", Path::new("examples/static/style"), ChangeDetection::FileInfoChange, EvictionPolicy::Never);
 
     let s = Server::new(addr, r);
-    s.start_http();
+    s.start_http().unwrap();
 }
\ No newline at end of file
@@ -0,0 +1,146 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use std::path::Path;
+
+use error::HttpError;
+use middleware::Middleware;
+use request::Request;
+use super::{Principal, Htpasswd, credentials, quote};
+
+/// Verifies user and password of a basic authentication
+pub trait BasicVerifier: Send + Sync + 'static {
+    /// returns the principal for valid credentials, ```None``` otherwise
+    fn verify(&self, user: &str, password: &str) -> Option<Principal>;
+}
+
+impl<F> BasicVerifier for F
+    where F: Send + Sync + 'static + Fn(&str, &str) -> Option<Principal>,
+{
+    fn verify(&self, user: &str, password: &str) -> Option<Principal> {
+        (*self)(user, password)
+    }
+}
+
+/// Guard for http basic authentication
+///
+/// ```
+/// # use rest_in_rust::*;
+/// # fn admin(_: &mut Request) -> Result<Response, HttpError> {
+/// #     Ok("".into())
+/// # }
+/// let mut r = Router::new();
+/// r.get("/admin", admin).middleware(BasicAuth::new("admin", |user: &str, password: &str| {
+///     if user == "admin" && password == "secret" {
+///         Some(Principal::new(user, AuthScheme::Basic))
+///     } else {
+///         None
+///     }
+/// }));
+/// ```
+pub struct BasicAuth {
+    realm: String,
+    verifier: Box<BasicVerifier>,
+}
+
+impl BasicAuth {
+    /// creates a new basic authentication guard for the given realm
+    pub fn new<S: Into<String>, V: BasicVerifier>(realm: S, verifier: V) -> Self {
+        BasicAuth { realm: realm.into(), verifier: Box::new(verifier) }
+    }
+
+    /// creates a new basic authentication guard verifying against an htpasswd file.
+    /// Supported are bcrypt and ```{SHA}``` hashes.
+    pub fn htpasswd<S: Into<String>, P: AsRef<Path>>(realm: S, path: P) -> Result<Self, HttpError> {
+        let htpasswd = Htpasswd::from_file(path)?;
+        Ok(BasicAuth::new(realm, htpasswd))
+    }
+
+    fn challenge(&self, msg: &str) -> HttpError {
+        let challenge = format!("Basic realm=\"{}\", charset=\"UTF-8\"", quote(&self.realm));
+        HttpError::unauthorized_challenge(challenge, msg)
+    }
+
+    fn authenticate(&self, req: &Request) -> Result<Principal, HttpError> {
+        let header = req.header(&::http::header::AUTHORIZATION).ok_or_else(|| self.challenge("Authentication required"))?;
+        let encoded = credentials(header, "Basic").ok_or_else(|| self.challenge("Basic authentication required"))?;
+
+        let decoded = ::base64::decode(encoded).map_err(|_| self.challenge("Invalid basic credentials"))?;
+        let decoded = String::from_utf8(decoded).map_err(|_| self.challenge("Invalid basic credentials"))?;
+        let index = decoded.find(':').ok_or_else(|| self.challenge("Invalid basic credentials"))?;
+        let (user, password) = decoded.split_at(index);
+
+        match self.verifier.verify(user, &password[1..]) {
+            Some(principal) => Ok(principal),
+            None => {
+                debug!("Basic authentication for {} failed", user);
+                Err(self.challenge("Invalid user or password"))
+            }
+        }
+    }
+}
+
+impl Middleware for BasicAuth {
+    fn before(&self, req: &mut Request) -> Result<(), HttpError> {
+        let principal = self.authenticate(req)?;
+        req.set_principal(principal);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use auth::AuthScheme;
+
+    fn guard() -> BasicAuth {
+        BasicAuth::new("test", |user: &str, password: &str| {
+            if user == "hans" && password == "wurst:brot" {
+                Some(Principal::new(user, AuthScheme::Basic))
+            } else {
+                None
+            }
+        })
+    }
+
+    fn request(authorization: Option<&str>) -> Request {
+        let mut builder = ::http::request::Builder::new();
+        builder.uri("/");
+        if let Some(value) = authorization {
+            builder.header(::http::header::AUTHORIZATION, value);
+        }
+        let req = builder.body(::body::Body::empty()).unwrap();
+        Request::from_http(req)
+    }
+
+    #[test]
+    fn valid_credentials() {
+        let value = format!("Basic {}", ::base64::encode("hans:wurst:brot"));
+        let mut req = request(Some(&value));
+        guard().before(&mut req).unwrap();
+        assert_eq!("hans", req.principal().unwrap().name.as_str());
+    }
+
+    #[test]
+    fn invalid_credentials() {
+        let value = format!("Basic {}", ::base64::encode("hans:käse"));
+        let mut req = request(Some(&value));
+        let err = guard().before(&mut req).unwrap_err();
+        assert_eq!(401, err.status.as_u16());
+        assert!(req.principal().is_none());
+    }
+
+    #[test]
+    fn missing_header_sends_challenge() {
+        let mut req = request(None);
+        let err = guard().before(&mut req).unwrap_err();
+        assert_eq!(401, err.status.as_u16());
+        let challenge = err.headers.get(::http::header::WWW_AUTHENTICATE).unwrap();
+        assert_eq!("Basic realm=\"test\", charset=\"UTF-8\"", challenge.to_str().unwrap());
+    }
+}
@@ -0,0 +1,108 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use error::HttpError;
+use middleware::Middleware;
+use request::Request;
+use super::{Principal, credentials, quote};
+
+/// Verifies a bearer token
+pub trait TokenVerifier: Send + Sync + 'static {
+    /// returns the principal for a valid token, ```None``` otherwise
+    fn verify(&self, token: &str) -> Option<Principal>;
+}
+
+impl<F> TokenVerifier for F
+    where F: Send + Sync + 'static + Fn(&str) -> Option<Principal>,
+{
+    fn verify(&self, token: &str) -> Option<Principal> {
+        (*self)(token)
+    }
+}
+
+/// Guard for bearer token authentication (RFC 6750)
+pub struct BearerAuth {
+    realm: String,
+    verifier: Box<TokenVerifier>,
+}
+
+impl BearerAuth {
+    /// creates a new bearer authentication guard for the given realm
+    pub fn new<S: Into<String>, V: TokenVerifier>(realm: S, verifier: V) -> Self {
+        BearerAuth { realm: realm.into(), verifier: Box::new(verifier) }
+    }
+
+    fn challenge(&self, error: Option<&str>, msg: &str) -> HttpError {
+        let challenge = match error {
+            Some(error) => format!("Bearer realm=\"{}\", error=\"{}\"", quote(&self.realm), error),
+            None => format!("Bearer realm=\"{}\"", quote(&self.realm)),
+        };
+        HttpError::unauthorized_challenge(challenge, msg)
+    }
+
+    fn authenticate(&self, req: &Request) -> Result<Principal, HttpError> {
+        let header = req.header(&::http::header::AUTHORIZATION).ok_or_else(|| self.challenge(None, "Authentication required"))?;
+        let token = credentials(header, "Bearer").ok_or_else(|| self.challenge(None, "Bearer token required"))?;
+
+        match self.verifier.verify(token) {
+            Some(principal) => Ok(principal),
+            None => {
+                debug!("Bearer token rejected");
+                Err(self.challenge(Some("invalid_token"), "Invalid token"))
+            }
+        }
+    }
+}
+
+impl Middleware for BearerAuth {
+    fn before(&self, req: &mut Request) -> Result<(), HttpError> {
+        let principal = self.authenticate(req)?;
+        req.set_principal(principal);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use auth::AuthScheme;
+
+    fn guard() -> BearerAuth {
+        BearerAuth::new("api", |token: &str| {
+            if token == "abc" {
+                Some(Principal::new("client", AuthScheme::Bearer).with_role("read"))
+            } else {
+                None
+            }
+        })
+    }
+
+    fn request(authorization: &str) -> Request {
+        let req = ::http::request::Builder::new().uri("/").header(::http::header::AUTHORIZATION, authorization)
+            .body(::body::Body::empty()).unwrap();
+        Request::from_http(req)
+    }
+
+    #[test]
+    fn valid_token() {
+        let mut req = request("Bearer abc");
+        guard().before(&mut req).unwrap();
+        let principal = req.principal().unwrap();
+        assert_eq!("client", principal.name.as_str());
+        assert!(principal.has_role("read"));
+    }
+
+    #[test]
+    fn invalid_token() {
+        let mut req = request("Bearer xyz");
+        let err = guard().before(&mut req).unwrap_err();
+        assert_eq!(401, err.status.as_u16());
+        let challenge = err.headers.get(::http::header::WWW_AUTHENTICATE).unwrap();
+        assert_eq!("Bearer realm=\"api\", error=\"invalid_token\"", challenge.to_str().unwrap());
+    }
+}
@@ -0,0 +1,130 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use error::HttpError;
+use super::{Principal, AuthScheme, BasicVerifier, constant_time_eq};
+
+/// User database in the apache htpasswd format.
+/// Supports bcrypt (```$2y$```, ```$2a$```, ```$2b$```) and ```{SHA}``` entries,
+/// other formats are skipped with a warning.
+#[derive(Debug, Clone, Default)]
+pub struct Htpasswd {
+    users: HashMap<String, Hash>,
+}
+
+#[derive(Debug, Clone)]
+enum Hash {
+    Bcrypt(String),
+    Sha1(Vec<u8>),
+}
+
+impl Htpasswd {
+    /// reads the given htpasswd file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, HttpError> {
+        let mut content = String::new();
+        File::open(path.as_ref())?.read_to_string(&mut content)?;
+        Ok(Htpasswd::parse(&content))
+    }
+
+    /// parses the content of a htpasswd file
+    pub fn parse(content: &str) -> Self {
+        let mut users = HashMap::new();
+        for (line_number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let index = match line.find(':') {
+                Some(index) => index,
+                None => {
+                    warn!("Ignoring invalid htpasswd line {}", line_number + 1);
+                    continue;
+                }
+            };
+            let (user, hash) = line.split_at(index);
+            let hash = &hash[1..];
+
+            let parsed = if hash.starts_with("$2y$") || hash.starts_with("$2a$") || hash.starts_with("$2b$") {
+                Some(Hash::Bcrypt(hash.to_string()))
+            } else if hash.starts_with("{SHA}") {
+                ::base64::decode(&hash[5..]).ok().map(Hash::Sha1)
+            } else {
+                None
+            };
+            match parsed {
+                Some(parsed) => {
+                    users.insert(user.to_string(), parsed);
+                }
+                None => warn!("Unsupported hash format for user {} in htpasswd line {}", user, line_number + 1),
+            }
+        }
+        Htpasswd { users }
+    }
+
+    /// returns true if the user exists and the password matches
+    pub fn check(&self, user: &str, password: &str) -> bool {
+        match self.users.get(user) {
+            Some(&Hash::Bcrypt(ref hash)) => ::bcrypt::verify(password, hash).unwrap_or(false),
+            Some(&Hash::Sha1(ref expected)) => {
+                let mut sha1 = ::sha1::Sha1::new();
+                sha1.update(password.as_bytes());
+                constant_time_eq(&sha1.digest().bytes(), expected)
+            }
+            None => false,
+        }
+    }
+}
+
+impl BasicVerifier for Htpasswd {
+    fn verify(&self, user: &str, password: &str) -> Option<Principal> {
+        if self.check(user, password) {
+            Some(Principal::new(user, AuthScheme::Basic))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the password of the sha user is "password", the one of the bcrypt user "secret"
+    const HTPASSWD: &'static str = "# comment\n\
+        sha:{SHA}W6ph5Mm5Pz8GgiULbPgzG37mj9g=\n\
+        bcrypt:$2y$05$gsU1Nt1ocA3d3pdDIUTlgeTDZp32413K.keBWYaXnoSZeUa6U1T8m\n\
+        md5:$apr1$abc$def\n";
+
+    #[test]
+    fn sha_entry() {
+        let htpasswd = Htpasswd::parse(HTPASSWD);
+        assert!(htpasswd.check("sha", "password"));
+        assert!(!htpasswd.check("sha", "Password"));
+    }
+
+    #[test]
+    fn bcrypt_entry() {
+        let htpasswd = Htpasswd::parse(HTPASSWD);
+        assert!(htpasswd.check("bcrypt", "secret"));
+        assert!(!htpasswd.check("bcrypt", "Secret"));
+        assert!(!htpasswd.check("bcrypt", ""));
+    }
+
+    #[test]
+    fn unsupported_and_unknown_users() {
+        let htpasswd = Htpasswd::parse(HTPASSWD);
+        assert!(!htpasswd.check("md5", "password"));
+        assert!(!htpasswd.check("nobody", "password"));
+        assert_eq!(2, htpasswd.users.len());
+    }
+}
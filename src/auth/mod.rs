@@ -0,0 +1,107 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Authentication guards for http basic and bearer authentication.
+//! The guards are middlewares, register them on a router, scope or route.
+//! On success the resolved principal is available via ```Request::principal```,
+//! otherwise a 401 with the matching ```WWW-Authenticate``` challenge is returned.
+
+mod basic;
+mod bearer;
+mod htpasswd;
+
+pub use self::basic::{BasicAuth, BasicVerifier};
+pub use self::bearer::{BearerAuth, TokenVerifier};
+pub use self::htpasswd::Htpasswd;
+
+/// The authentication scheme a principal was resolved with
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum AuthScheme {
+    /// http basic authentication, user and password
+    Basic,
+    /// bearer token authentication
+    Bearer,
+}
+
+/// An authenticated user or client
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Principal {
+    /// user name or token subject
+    pub name: String,
+    /// scheme used for the authentication
+    pub scheme: AuthScheme,
+    /// roles of this principal, filled by the verifier
+    pub roles: Vec<String>,
+}
+
+impl Principal {
+    /// creates a new principal without roles
+    pub fn new<S: Into<String>>(name: S, scheme: AuthScheme) -> Self {
+        Principal { name: name.into(), scheme, roles: Vec::new() }
+    }
+
+    /// adds the given role and returns self
+    pub fn with_role<S: Into<String>>(mut self, role: S) -> Self {
+        self.roles.push(role.into());
+        self
+    }
+
+    /// returns true if the principal has the given role
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+}
+
+/// splits an authorization header value into scheme and credentials if the scheme matches (case insensitive)
+fn credentials<'a>(header: &'a str, scheme: &str) -> Option<&'a str> {
+    let header = header.trim();
+    let index = header.find(' ')?;
+    let (found_scheme, credentials) = header.split_at(index);
+    if found_scheme.eq_ignore_ascii_case(scheme) {
+        Some(credentials.trim())
+    } else {
+        None
+    }
+}
+
+/// compares without returning early, used to compare secrets
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut result = 0u8;
+    for (x, y) in a.iter().zip(b.iter()) {
+        result |= x ^ y;
+    }
+    result == 0
+}
+
+fn quote(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_credentials() {
+        assert_eq!(Some("abc"), credentials("Bearer abc", "Bearer"));
+        assert_eq!(Some("abc"), credentials("bearer   abc ", "Bearer"));
+        assert_eq!(None, credentials("Basic abc", "Bearer"));
+        assert_eq!(None, credentials("Bearer", "Bearer"));
+    }
+
+    #[test]
+    fn compare_secrets() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secrets"));
+    }
+}
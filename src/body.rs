@@ -81,7 +81,7 @@ impl Body {
     pub fn into_inner(self) -> Option<Vec<u8>> {
         self.0
     }
-    ///Converts a string body to any serde deserializable body
+    ///Converts a string body to any serde deserializable body, ignoring the ```Content-Type```. See ```Request::body_as``` for bodies in other formats
     /// 
     /// ```
     /// extern crate rest_in_rust;
@@ -102,8 +102,8 @@ impl Body {
     /// ```
     pub fn to_json<T>(&self) -> Result<T, HttpError>
         where T: ::serde::de::DeserializeOwned {
-        use serde_json::from_str;
-        use serde_json::Error;
+        use serde_json::Deserializer;
+        use serde_json::error::Category;
 
         let ref vec = match self.0 {
             None => Err(HttpError::bad_request("No body given, cannot parse as json")),
@@ -118,14 +118,18 @@ impl Body {
             Ok(val) => Ok(val),
         }?;
 
-        let b: Result<T, Error> = from_str(string_value);
-        match b {
-            Ok(cmd) => Ok(cmd),
-            Err(e) => {
-                error!("Could not parse input as json: {:?}", e);
-                Err(HttpError::bad_request("Could not parse input as json"))
-            }
-        }
+        let mut de = Deserializer::from_str(string_value);
+        let value = ::serde_path_to_error::deserialize(&mut de)
+            .map_err(|e| {
+                let path = e.path().to_string();
+                let e = e.into_inner();
+                match e.classify() {
+                    Category::Data => ::validate::data_error("json", &path, e),
+                    Category::Syntax | Category::Eof | Category::Io => ::validate::syntax_error("json", e),
+                }
+            })?;
+        de.end().map_err(|e| ::validate::syntax_error("json", e))?;
+        Ok(value)
     }
 
     ///Helper method to convert the body to a string
@@ -0,0 +1,299 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Cross origin resource sharing (CORS) configuration.
+//! Register it via ```Router::cors```, preflight requests are answered automatically
+//! and all responses (including errors) get the CORS headers.
+//!
+//! ```
+//! # use rest_in_rust::*;
+//! # fn hello(_: &mut Request) -> Result<Response, HttpError> {
+//! #     Ok("hello".into())
+//! # }
+//! let mut r = Router::new();
+//! r.cors(Cors::new()
+//!     .allow_origin("https://app.example.com")
+//!     .allow_origin("https://*.example.org")
+//!     .allow_credentials(true)
+//!     .max_age(3600));
+//! r.post("/hello", hello);
+//! ```
+
+use http::Method;
+use http::header::{self, HeaderValue, HeaderMap};
+use std::sync::Arc;
+
+use error::HttpError;
+use handler::Handler;
+use middleware::Middleware;
+use request::Request;
+use response::Response;
+
+/// CORS configuration
+#[derive(Debug, Clone)]
+pub struct Cors {
+    origins: Option<Vec<String>>,
+    methods: Vec<Method>,
+    headers: Option<Vec<String>>,
+    exposed_headers: Vec<String>,
+    credentials: bool,
+    max_age: Option<u32>,
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Cors {
+            origins: Some(Vec::new()),
+            methods: vec![Method::GET, Method::HEAD, Method::POST, Method::PUT, Method::PATCH, Method::DELETE],
+            headers: None,
+            exposed_headers: Vec::new(),
+            credentials: false,
+            max_age: None,
+        }
+    }
+}
+
+impl Cors {
+    /// creates a new configuration which does not allow any origin yet.
+    /// All requested headers are allowed until ```allow_header``` is called.
+    pub fn new() -> Self {
+        Cors::default()
+    }
+
+    /// allows the given origin, a ```*``` in the origin matches any characters
+    /// eg. ```https://*.example.com```
+    pub fn allow_origin<S: Into<String>>(mut self, origin: S) -> Self {
+        let origin = origin.into();
+        if let Some(ref mut origins) = self.origins {
+            origins.push(origin);
+        }
+        self
+    }
+
+    /// allows every origin
+    pub fn allow_any_origin(mut self) -> Self {
+        self.origins = None;
+        self
+    }
+
+    /// sets the allowed methods, default are GET, HEAD, POST, PUT, PATCH and DELETE
+    pub fn allow_methods(mut self, methods: &[Method]) -> Self {
+        self.methods = methods.to_vec();
+        self
+    }
+
+    /// allows the given request header, once called only explicitly allowed headers are accepted
+    pub fn allow_header<S: Into<String>>(mut self, name: S) -> Self {
+        let name = name.into().to_lowercase();
+        match self.headers {
+            Some(ref mut headers) => headers.push(name),
+            None => self.headers = Some(vec![name]),
+        }
+        self
+    }
+
+    /// exposes the given response header to the client
+    pub fn expose_header<S: Into<String>>(mut self, name: S) -> Self {
+        self.exposed_headers.push(name.into());
+        self
+    }
+
+    /// allows cookies and authorization headers
+    pub fn allow_credentials(mut self, credentials: bool) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    /// sets how long (in seconds) the client may cache a preflight response
+    pub fn max_age(mut self, seconds: u32) -> Self {
+        self.max_age = Some(seconds);
+        self
+    }
+
+    fn origin_allowed(&self, origin: &str) -> bool {
+        match self.origins {
+            None => true,
+            Some(ref origins) => origins.iter().any(|pattern| matches(pattern.as_bytes(), origin.as_bytes())),
+        }
+    }
+
+    fn add_origin_headers(&self, origin: &str, headers: &mut HeaderMap<HeaderValue>) {
+        let allow_origin = if self.origins.is_none() && !self.credentials {
+            Some(HeaderValue::from_static("*"))
+        } else {
+            HeaderValue::from_str(origin).ok()
+        };
+        if let Some(allow_origin) = allow_origin {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+        }
+        if self.origins.is_some() || self.credentials {
+            headers.append(header::VARY, HeaderValue::from_static("Origin"));
+        }
+        if self.credentials {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, HeaderValue::from_static("true"));
+        }
+    }
+
+    /// answers a preflight request, returns a response without CORS headers if the request is not allowed
+    fn preflight(&self, req: &Request) -> Result<Response, HttpError> {
+        let mut response = Response::builder().status(::http::StatusCode::NO_CONTENT).build()?;
+
+        let origin = match req.header(&header::ORIGIN) {
+            Some(origin) if self.origin_allowed(origin) => origin,
+            other => {
+                debug!("Rejecting preflight from origin {:?}", other);
+                return Ok(response);
+            }
+        };
+        let method_allowed = req.header(&header::ACCESS_CONTROL_REQUEST_METHOD)
+            .and_then(|m| Method::from_bytes(m.as_bytes()).ok())
+            .map(|m| self.methods.contains(&m))
+            .unwrap_or(false);
+        if !method_allowed {
+            debug!("Rejecting preflight for method {:?}", req.header(&header::ACCESS_CONTROL_REQUEST_METHOD));
+            return Ok(response);
+        }
+
+        let requested_headers = req.header(&header::ACCESS_CONTROL_REQUEST_HEADERS).unwrap_or("");
+        let allowed_headers = match self.headers {
+            None => requested_headers.to_string(),
+            Some(ref allowed) => {
+                let rejected = requested_headers.split(',').map(|h| h.trim().to_lowercase())
+                    .filter(|h| !h.is_empty())
+                    .find(|h| !allowed.contains(h));
+                if let Some(rejected) = rejected {
+                    debug!("Rejecting preflight because of header {}", rejected);
+                    return Ok(response);
+                }
+                allowed.join(", ")
+            }
+        };
+
+        {
+            let headers = response.headers_mut();
+            self.add_origin_headers(origin, headers);
+            let methods: Vec<&str> = self.methods.iter().map(|m| m.as_str()).collect();
+            headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, HeaderValue::from_str(&methods.join(", "))?);
+            if !allowed_headers.is_empty() {
+                headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, HeaderValue::from_str(&allowed_headers)?);
+            }
+            if let Some(max_age) = self.max_age {
+                headers.insert(header::ACCESS_CONTROL_MAX_AGE, HeaderValue::from_str(&max_age.to_string())?);
+            }
+        }
+        Ok(response)
+    }
+}
+
+impl Middleware for Cors {
+    fn after(&self, req: &Request, resp: &mut Response) {
+        let origin = match req.header(&header::ORIGIN) {
+            Some(origin) => origin,
+            None => return,
+        };
+        if !self.origin_allowed(origin) || resp.headers().contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN) {
+            return;
+        }
+        let headers = resp.headers_mut();
+        self.add_origin_headers(origin, headers);
+        if !self.exposed_headers.is_empty() {
+            if let Ok(value) = HeaderValue::from_str(&self.exposed_headers.join(", ")) {
+                headers.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, value);
+            }
+        }
+    }
+}
+
+/// Handler registered for OPTIONS on every path that has no own OPTIONS route
+pub struct PreflightHandler {
+    cors: Arc<Cors>,
+}
+
+impl PreflightHandler {
+    pub fn new(cors: Arc<Cors>) -> Self {
+        PreflightHandler { cors }
+    }
+}
+
+impl Handler for PreflightHandler {
+    fn handle(&self, req: &mut Request) -> Result<Response, HttpError> {
+        self.cors.preflight(req)
+    }
+}
+
+/// simple glob matching, ```*``` matches any sequence of characters
+fn matches(pattern: &[u8], value: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => value.is_empty(),
+        Some((&b'*', rest)) => (0..value.len() + 1).any(|i| matches(rest, &value[i..])),
+        Some((c, rest)) => match value.split_first() {
+            Some((v, value_rest)) if v.eq_ignore_ascii_case(c) => matches(rest, value_rest),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: Method, headers: &[(&'static str, &'static str)]) -> Request {
+        let mut builder = ::http::request::Builder::new();
+        builder.method(method).uri("/");
+        for &(name, value) in headers.iter() {
+            builder.header(name, value);
+        }
+        let req = builder.body(::body::Body::empty()).unwrap();
+        Request::from_http(req)
+    }
+
+    #[test]
+    fn origin_patterns() {
+        assert!(matches(b"https://*.example.com", b"https://api.example.com"));
+        assert!(matches(b"https://example.com", b"https://EXAMPLE.com"));
+        assert!(!matches(b"https://*.example.com", b"https://example.com"));
+        assert!(!matches(b"https://example.com", b"https://example.com.evil.org"));
+    }
+
+    #[test]
+    fn preflight_allowed() {
+        let cors = Cors::new().allow_origin("https://*.example.com").allow_header("Content-Type").max_age(60);
+        let req = request(Method::OPTIONS, &[("origin", "https://app.example.com"),
+            ("access-control-request-method", "PUT"), ("access-control-request-headers", "content-type")]);
+        let response = cors.preflight(&req).unwrap();
+
+        assert_eq!(204, response.status().as_u16());
+        let headers = response.headers();
+        assert_eq!("https://app.example.com", headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap());
+        assert_eq!("content-type", headers.get(header::ACCESS_CONTROL_ALLOW_HEADERS).unwrap());
+        assert_eq!("60", headers.get(header::ACCESS_CONTROL_MAX_AGE).unwrap());
+    }
+
+    #[test]
+    fn preflight_rejected() {
+        let cors = Cors::new().allow_origin("https://app.example.com").allow_header("content-type");
+        let req = request(Method::OPTIONS, &[("origin", "https://evil.org"), ("access-control-request-method", "PUT")]);
+        assert!(cors.preflight(&req).unwrap().headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+
+        let req = request(Method::OPTIONS, &[("origin", "https://app.example.com"),
+            ("access-control-request-method", "PUT"), ("access-control-request-headers", "x-secret")]);
+        assert!(cors.preflight(&req).unwrap().headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+
+    #[test]
+    fn error_response_gets_headers() {
+        let cors = Cors::new().allow_any_origin().expose_header("X-Total");
+        let req = request(Method::GET, &[("origin", "https://app.example.com")]);
+        let mut response = Response::from(HttpError::bad_request("invalid"));
+        cors.after(&req, &mut response);
+
+        assert_eq!("*", response.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap());
+        assert_eq!("X-Total", response.headers().get(header::ACCESS_CONTROL_EXPOSE_HEADERS).unwrap());
+    }
+}
@@ -6,26 +6,31 @@
 // <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
+use std::error::Error;
 use http::{StatusCode, HeaderMap};
 use http::header::HeaderValue;
 
+use ::problem::ProblemDetails;
 use ::response::Response;
 
 /// RestInRust's error type
-/// Suupports various conversions out of the box
-/// 
-/// * conversion from any string
-/// * ::http::Error
-/// * ::std::io::Error
-/// * ::http::header::InvalidHeaderValue
-/// * ::serde_json::Error
-/// * ::std::str::Utf8Error
-/// * ::http::uri::InvalidUri
+/// Any ```std::error::Error```, boxed error or string converts with ```?```.
+/// The converted error is kept as ```source``` for logging, the client only gets the generic ```msg```
+/// of a 500, except for ```Utf8Error``` and ```InvalidUri``` which are answered with 400.
+/// Strings convert the same way, so ```"No user".into()``` answers "Internal server error";
+/// use ```HttpError::internal_server_error("No user")``` or another shortcut to send the message to the client.
+/// ```HttpError``` itself does not implement ```std::error::Error```, use ```sources()``` to inspect the cause.
+///
+/// Problem details for ```application/problem+json``` responses can be added via the builder methods, eg.
+/// ```HttpError::bad_request("Invalid user").problem_type("https://example.com/problems/invalid-user")```
 #[derive(Debug)]
 pub struct HttpError {
     pub status: StatusCode,
     pub msg: String,
     pub headers: HeaderMap<HeaderValue>,
+    pub problem: Option<ProblemDetails>,
+    /// internal cause of the error, logged but never sent to the client
+    pub source: Option<Box<Error + Send + Sync>>,
 }
 
 impl ::std::fmt::Display for HttpError {
@@ -34,12 +39,6 @@ impl ::std::fmt::Display for HttpError {
     }
 }
 
-impl ::std::error::Error for HttpError {
-    fn description(&self) -> &str {
-        self.msg.as_str()
-    }
-}
-
 impl HttpError {
     ///Shortcut function to create a 404 not found error
     pub fn not_found<S: Into<String>>(resource: S) -> Self {
@@ -57,17 +56,140 @@ impl HttpError {
         Self::internal_error(StatusCode::UNAUTHORIZED, resource)
     }
 
-    ///Shortcut function to create a 500 internal server error
+    ///Shortcut function to create a 401 unauthorized error with a ```WWW-Authenticate``` challenge
+    ///eg. ```Basic realm="admin"```
+    pub fn unauthorized_challenge<C: AsRef<str>, S: Into<String>>(challenge: C, resource: S) -> Self {
+        let mut error = Self::internal_error(StatusCode::UNAUTHORIZED, resource);
+        match HeaderValue::from_str(challenge.as_ref()) {
+            Ok(value) => {
+                error.headers.insert(::http::header::WWW_AUTHENTICATE, value);
+            }
+            Err(e) => error!("Invalid authentication challenge {}: {:?}", challenge.as_ref(), e),
+        }
+        error
+    }
+
+    ///Shortcut function to create a 429 too many requests error
+    pub fn too_many_requests<S: Into<String>>(resource: S) -> Self {
+        Self::internal_error(StatusCode::TOO_MANY_REQUESTS, resource)
+    }
+
+    ///Shortcut function to create a 406 not acceptable error
+    pub fn not_acceptable<S: Into<String>>(resource: S) -> Self {
+        Self::internal_error(StatusCode::NOT_ACCEPTABLE, resource)
+    }
+
+    ///Shortcut function to create a 413 payload too large error
+    pub fn payload_too_large<S: Into<String>>(resource: S) -> Self {
+        Self::internal_error(StatusCode::PAYLOAD_TOO_LARGE, resource)
+    }
+
+    ///Shortcut function to create a 415 unsupported media type error
+    pub fn unsupported_media_type<S: Into<String>>(resource: S) -> Self {
+        Self::internal_error(StatusCode::UNSUPPORTED_MEDIA_TYPE, resource)
+    }
+
+    ///Shortcut function to create a 416 range not satisfiable error
+    pub fn range_not_satisfiable<S: Into<String>>(resource: S) -> Self {
+        Self::internal_error(StatusCode::RANGE_NOT_SATISFIABLE, resource)
+    }
+
+    ///Shortcut function to create a 422 unprocessable entity error
+    pub fn unprocessable_entity<S: Into<String>>(resource: S) -> Self {
+        Self::internal_error(StatusCode::UNPROCESSABLE_ENTITY, resource)
+    }
+
+    ///Shortcut function to create a 431 request header fields too large error
+    pub fn header_too_large<S: Into<String>>(resource: S) -> Self {
+        Self::internal_error(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE, resource)
+    }
+
+    ///Shortcut function to create a 500 internal server error, the message is sent to the client
     pub fn internal_server_error<S: Into<String>>(resource: S) -> Self {
         Self::internal_error(StatusCode::INTERNAL_SERVER_ERROR, resource)
     }
 
+    /// sets the uri identifying the problem type
+    pub fn problem_type<S: Into<String>>(mut self, type_uri: S) -> Self {
+        self.problem_mut().type_uri = Some(type_uri.into());
+        self
+    }
+
+    /// sets a short summary of the problem type, the reason phrase of the status is used otherwise
+    pub fn title<S: Into<String>>(mut self, title: S) -> Self {
+        self.problem_mut().title = Some(title.into());
+        self
+    }
+
+    /// sets the explanation of the problem, the message is used otherwise
+    pub fn detail<S: Into<String>>(mut self, detail: S) -> Self {
+        self.problem_mut().detail = Some(detail.into());
+        self
+    }
+
+    /// sets the uri identifying this occurrence of the problem
+    pub fn instance<S: Into<String>>(mut self, instance: S) -> Self {
+        self.problem_mut().instance = Some(instance.into());
+        self
+    }
+
+    /// adds an additional member to the problem details, eg. a list of invalid fields
+    pub fn extension<K: Into<String>, V: ::serde::Serialize>(mut self, key: K, value: V) -> Self {
+        let key = key.into();
+        match ::serde_json::to_value(value) {
+            Ok(value) => {
+                self.problem_mut().extensions.insert(key, value);
+            }
+            Err(e) => error!("Could not serialize problem extension {}: {}", key, e),
+        }
+        self
+    }
+
+    /// keeps the internal cause of a hand made error for logging, eg. ```HttpError::not_found("No such user").with_source(db_error)```
+    pub fn with_source<E: Into<Box<Error + Send + Sync>>>(mut self, source: E) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    /// the source and its causes
+    pub fn sources(&self) -> Sources {
+        Sources { next: self.source.as_ref().map(|source| &**source as &(Error + 'static)) }
+    }
+
+    /// status and message followed by the source chain, eg. ```500 Internal Server Error: Internal server error (query failed: connection refused)```
+    pub fn log_message(&self) -> String {
+        let sources: Vec<String> = self.sources().map(|e| e.to_string()).collect();
+        if sources.is_empty() {
+            self.to_string()
+        } else {
+            format!("{} ({})", self, sources.join(": "))
+        }
+    }
+
+    /// logs errors with a source, server errors as error and client errors as debug
+    pub(crate) fn log(&self) {
+        if self.source.is_none() {
+            return;
+        }
+        if self.status.is_server_error() {
+            error!("{}", self.log_message());
+        } else {
+            debug!("{}", self.log_message());
+        }
+    }
+
+    fn problem_mut(&mut self) -> &mut ProblemDetails {
+        self.problem.get_or_insert_with(ProblemDetails::default)
+    }
+
     fn internal_error<S: Into<String>>(status: StatusCode, msg: S) -> Self {
         let msg: String = msg.into();
         HttpError {
             status: status,
             msg: msg,
-            headers: HeaderMap::new()
+            headers: HeaderMap::new(),
+            problem: None,
+            source: None,
         }
     }
 }
@@ -80,6 +202,7 @@ impl From<HttpError> for ::http::Response<::body::Body> {
 
 impl From<HttpError> for Response {
     fn from(err: HttpError) -> Response {
+        err.log();
         let r = Response::builder().header_map(err.headers).status(err.status).body_vec(err.msg.into_bytes()).build();
         match r {
             Ok(res) => res,
@@ -92,73 +215,82 @@ impl From<HttpError> for Response {
     }
 }
 
-impl<'a> From<&'a str> for HttpError {
-    fn from(msg: &'a str) -> Self {
-        HttpError { status: StatusCode::INTERNAL_SERVER_ERROR, headers: HeaderMap::new(), msg: msg.into() }
+impl<E: Into<Box<Error + Send + Sync>>> From<E> for HttpError {
+    /// ```Utf8Error``` and ```InvalidUri``` are answered with 400, any other error with a generic 500.
+    /// The error is kept as source and only logged
+    fn from(error: E) -> Self {
+        let source = error.into();
+        let err = if source.is::<::std::str::Utf8Error>() || source.is::<::http::uri::InvalidUri>() {
+            HttpError::bad_request(source.to_string())
+        } else {
+            HttpError::internal_server_error("Internal server error")
+        };
+        err.with_source(source)
     }
 }
 
-impl From<String> for HttpError {
-    fn from(msg: String) -> Self {
-        HttpError { status: StatusCode::INTERNAL_SERVER_ERROR, headers: HeaderMap::new(), msg: msg }
-    }
+/// Iterator over the source of an ```HttpError``` and its causes
+pub struct Sources<'a> {
+    next: Option<&'a (Error + 'static)>,
 }
 
-impl From<::http::Error> for HttpError {
-    fn from(error: ::http::Error) -> Self {
-        use std::error::Error;
-        error!("Http parsing error. {}", error.description());
-        HttpError::internal_server_error(error.description())
-    }
-}
+impl<'a> Iterator for Sources<'a> {
+    type Item = &'a (Error + 'static);
 
-impl From<::std::io::Error> for HttpError {
-    fn from(error: ::std::io::Error) -> Self {
-        use std::error::Error;
-        error!("General IO error. {}", error.description());
-        HttpError::internal_server_error(error.description())
+    fn next(&mut self) -> Option<Self::Item> {
+        let current = self.next?;
+        self.next = current.source();
+        Some(current)
     }
 }
 
-impl From<::http::header::InvalidHeaderValue> for HttpError {
-    fn from(error: ::http::header::InvalidHeaderValue) -> Self {
-        use std::error::Error;
-        error!("Could not parse header value. {}", error.description());
-        HttpError::internal_server_error(error.description())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct QueryFailed(::std::io::Error);
+
+    impl ::std::fmt::Display for QueryFailed {
+        fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+            write!(f, "query failed")
+        }
     }
-}
 
-impl From<::serde_json::Error> for HttpError {
-    fn from(error: ::serde_json::Error) -> Self {
-        use std::error::Error;
+    impl Error for QueryFailed {
+        fn source(&self) -> Option<&(Error + 'static)> {
+            Some(&self.0)
+        }
+    }
 
-        error!("Could not deserialize. {}", error.description());
-        HttpError::internal_server_error(error.description())
+    fn query() -> Result<(), HttpError> {
+        Err(QueryFailed(::std::io::Error::new(::std::io::ErrorKind::Other, "connection to db:5432 refused")))?
     }
-}
 
-impl From<::std::str::Utf8Error> for HttpError {
-    fn from(error: ::std::str::Utf8Error) -> Self {
-        use std::error::Error;
+    #[test]
+    fn source_chain() {
+        let err = query().unwrap_err();
+        assert_eq!(500, err.status.as_u16());
+        assert_eq!("Internal server error", err.msg);
+        let sources: Vec<String> = err.sources().map(|e| e.to_string()).collect();
+        assert_eq!(vec!["query failed", "connection to db:5432 refused"], sources);
+        assert_eq!("500 Internal Server Error: Internal server error (query failed: connection to db:5432 refused)", err.log_message());
 
-        error!("Invalid utf8 string. {}", error.description());
-        HttpError::bad_request(error.description())
+        let body = Response::from(err).into_vec().unwrap();
+        assert_eq!(b"Internal server error".to_vec(), body);
     }
-}
 
-impl From<::http::uri::InvalidUri> for HttpError {
-    fn from(invalid_uri: ::http::uri::InvalidUri) -> Self {
-        use std::error::Error;
+    #[test]
+    fn conversions() {
+        let err: HttpError = "secret detail".into();
+        assert_eq!("Internal server error", err.msg);
+        assert_eq!("secret detail", err.sources().next().unwrap().to_string());
+
+        let err: HttpError = ::std::str::from_utf8(&[0xff]).unwrap_err().into();
+        assert_eq!(400, err.status.as_u16());
 
-        error!("Invalid URI. {}", invalid_uri.description());
-        HttpError::bad_request(invalid_uri.description())
+        let err = HttpError::not_found("No such user").with_source("row 7 missing");
+        assert_eq!("No such user", err.msg);
+        assert_eq!(1, err.sources().count());
     }
 }
-
-//fixme would be awesome if this works
-//impl<T: ::std::error::Error> From<T> for HttpError {
-//    fn from(error: T) -> Self {
-//        use std::error::Error;
-//        HttpError::internal_server_error(error.description())
-//    }
-//}
\ No newline at end of file
@@ -6,6 +6,9 @@
 // <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
+use std::any::Any;
+use std::sync::Arc;
+use http::Method;
 use request::Request;
 use error::HttpError;
 use response::Response;
@@ -24,3 +27,28 @@ impl<F> Handler for F
         (*self)(req)
     }
 }
+
+/// Information about a panicking handler, passed to the panic hook of the router
+#[derive(Debug, Clone)]
+pub struct HandlerPanic {
+    pub method: Method,
+    /// path of the route, not the requested path
+    pub path: String,
+    pub request_id: String,
+    /// the panic message if the panic was raised with a string
+    pub message: String,
+}
+
+/// Called with every handler panic, see ```Router::panic_hook```
+pub type PanicHook = Arc<Box<Fn(&HandlerPanic) + Send + Sync>>;
+
+/// extracts the message of ```panic!("...")```, other payloads can not be displayed
+pub(crate) fn panic_message(payload: &Box<Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Box<Any>".to_string()
+    }
+}
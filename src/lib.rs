@@ -23,12 +23,13 @@
 //! 
 //! ### Https
 //! 
+//! Works with both tls backends, see ```server::TlsConfig``` for sni and client certificates.
+//! 
 //! ```rust,no_run
 //! extern crate rest_in_rust;
-//! extern crate native_tls;
 //! 
 //! use rest_in_rust::*;
-//! use native_tls::Pkcs12;
+//! use rest_in_rust::server::{TlsConfig, Identity};
 //! 
 //! fn hello_world(_: &mut Request) -> Result<Response, HttpError> {
 //!     Ok("hello encryption".into())
@@ -40,9 +41,8 @@
 //!     r.get("/", hello_world);
 //!     let  s = Server::new(addr,r);
 //! 
-//!     let der = include_bytes!("../examples/certificate.p12");
-//!     let cert = Pkcs12::from_der(der, "password").unwrap();
-//!     s.start_https(cert);
+//!     let identity = Identity::from_pem_files("cert.pem", "key.pem").unwrap();
+//!     s.start_https(TlsConfig::new(identity)).unwrap();
 //! }
 //! ```
 //! 
@@ -69,7 +69,7 @@
 //!     r.get("/say/*text", say);
 //! 
 //!     let s = Server::new(addr, r);
-//!     s.start_http();
+//!     s.start_http().unwrap();
 //! }
 //! ```
 //! 
@@ -103,7 +103,7 @@
 //!     r.get("/", get_json);
 //! 
 //!     let s = Server::new(addr, r);
-//!     s.start_http();
+//!     s.start_http().unwrap();
 //! }
 //! ```
 //! 
@@ -133,7 +133,7 @@
 //!     r.get("/", query_param);
 //! 
 //!     let s = Server::new(addr,r);
-//!     s.start_http();
+//!     s.start_http().unwrap();
 //! }
 //! ```
 //! 
@@ -153,7 +153,7 @@
 //!     r.static_path_cached("/style", Path::new("examples/static/style"), ChangeDetection::FileInfoChange, EvictionPolicy::Never);
 //! 
 //!     let s = Server::new(addr, r);
-//!     s.start_http();
+//!     s.start_http().unwrap();
 //! } 
 //! ```
 //! 
@@ -206,6 +206,17 @@ extern crate url;
 extern crate route_recognizer;
 extern crate serde;
 extern crate serde_json;
+extern crate serde_path_to_error;
+extern crate erased_serde;
+extern crate serde_urlencoded;
+extern crate regex;
+#[cfg(feature = "msgpack")]
+extern crate rmp_serde;
+#[cfg(feature = "cbor")]
+extern crate serde_cbor;
+#[cfg(feature = "xml")]
+extern crate serde_xml_rs;
+extern crate toml;
 #[allow(unused)]
 #[macro_use]
 #[allow(unused)]
@@ -214,8 +225,16 @@ extern crate futures;
 extern crate futures_cpupool;
 #[macro_use]
 extern crate log;
+#[cfg(feature = "tls-native")]
 extern crate native_tls;
+#[cfg(feature = "tls-native")]
 extern crate tokio_tls;
+#[cfg(feature = "tls-rustls")]
+extern crate rustls;
+#[cfg(feature = "tls-rustls")]
+extern crate tokio_rustls;
+#[cfg(feature = "tls-rustls")]
+extern crate webpki;
 extern crate tokio_service;
 extern crate tokio_proto;
 extern crate state;
@@ -223,9 +242,20 @@ extern crate mime_sniffer;
 extern crate mime_guess;
 extern crate http;
 extern crate tokio_io;
+extern crate tokio_core;
 extern crate bytes;
 extern crate httparse;
 extern crate sha1;
+extern crate base64;
+extern crate bcrypt;
+#[cfg(unix)]
+extern crate signal_hook;
+#[cfg(unix)]
+extern crate tokio_uds;
+#[cfg(unix)]
+extern crate libc;
+#[cfg(all(feature = "tls-native", not(any(target_os = "macos", target_os = "windows", target_os = "ios"))))]
+extern crate openssl;
 #[cfg(test)]
 extern crate spectral;
 #[cfg(test)]
@@ -240,15 +270,27 @@ pub mod request;
 pub mod response;
 pub mod handler;
 pub mod error;
+pub mod problem;
+pub mod negotiate;
+pub mod validate;
 pub mod traits;
 pub mod body;
+pub mod middleware;
+pub mod auth;
+pub mod cors;
+pub mod ratelimit;
+pub mod metrics;
 
-pub use router::{Router,ChangeDetection,EvictionPolicy};
+pub use router::{Router,ChangeDetection,EvictionPolicy,RouteLimits,ErrorRenderer,StatusRange};
 pub use error::HttpError;
-pub use handler::Handler;
+pub use handler::{Handler, HandlerPanic};
 pub use request::Request;
 pub use response::{ResponseBuilder,Response};
 pub use server::Server;
 pub use server::tester::ServerTester;
 pub use traits::{FromRequest, FromRequestAsRef};
-pub use body::Body;
\ No newline at end of file
+pub use body::Body;
+pub use middleware::Middleware;
+pub use auth::{AuthScheme, BasicAuth, BearerAuth, Principal};
+pub use cors::Cors;
+pub use ratelimit::{RateLimiter, RateLimitKey};
\ No newline at end of file
@@ -0,0 +1,236 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Built in metrics exposed in the prometheus text format.
+//! Enable them by registering the metrics route via ```Router::metrics```.
+//!
+//! ```
+//! # use rest_in_rust::*;
+//! let mut r = Router::new();
+//! r.metrics("/metrics");
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use error::HttpError;
+use handler::Handler;
+use request::Request;
+use response::Response;
+use router::staticfile::StaticFileCache;
+
+const BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// label used for requests that did not match any route
+pub const UNMATCHED_ROUTE: &'static str = "<unmatched>";
+
+/// label used for all methods not defined by RFC 7231 and RFC 5789, keeps the label cardinality bounded
+pub const OTHER_METHOD: &'static str = "OTHER";
+
+const METHODS: [&'static str; 9] = ["GET", "HEAD", "POST", "PUT", "DELETE", "CONNECT", "OPTIONS", "TRACE", "PATCH"];
+
+#[derive(Debug, Default, Clone)]
+struct Histogram {
+    buckets: [u64; 11],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        for (bucket, upper) in self.buckets.iter_mut().zip(BUCKETS.iter()) {
+            if seconds <= *upper {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+/// Collected server metrics
+pub struct Metrics {
+    requests: Mutex<BTreeMap<(String, String, u16), u64>>,
+    durations: Mutex<BTreeMap<(String, String), Histogram>>,
+    in_flight: AtomicUsize,
+    connections: AtomicUsize,
+    queued: AtomicUsize,
+    static_file_cache: Arc<StaticFileCache>,
+}
+
+impl Metrics {
+    pub(crate) fn new(static_file_cache: Arc<StaticFileCache>) -> Self {
+        Metrics {
+            requests: Mutex::new(BTreeMap::new()),
+            durations: Mutex::new(BTreeMap::new()),
+            in_flight: AtomicUsize::new(0),
+            connections: AtomicUsize::new(0),
+            queued: AtomicUsize::new(0),
+            static_file_cache,
+        }
+    }
+
+    /// called when a request is handed to its route
+    pub fn request_started(&self) {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// called with the final response status of a request started with ```request_started```
+    pub fn request_finished(&self, route: &str, method: &str, status: u16, duration: Duration) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        self.observe(route, method, status, duration);
+    }
+
+    /// records a request that never reached a handler, non standard methods are recorded as ```OTHER```
+    pub fn observe(&self, route: &str, method: &str, status: u16, duration: Duration) {
+        let method = method_label(method);
+        let seconds = duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1_000_000_000.0;
+        {
+            let mut requests = self.requests.lock().unwrap();
+            *requests.entry((route.to_string(), method.to_string(), status)).or_insert(0) += 1;
+        }
+        let mut durations = self.durations.lock().unwrap();
+        durations.entry((route.to_string(), method.to_string())).or_insert_with(Histogram::default).observe(seconds);
+    }
+
+    /// called when a request is queued for the worker pool
+    pub fn queued(&self) {
+        self.queued.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// called when a worker starts processing a queued request
+    pub fn dequeued(&self) {
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// renders all metrics in the prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP rir_http_requests_total Total number of handled http requests.\n");
+        out.push_str("# TYPE rir_http_requests_total counter\n");
+        for (&(ref route, ref method, status), count) in self.requests.lock().unwrap().iter() {
+            let _ = writeln!(out, "rir_http_requests_total{{route=\"{}\",method=\"{}\",status=\"{}\"}} {}", escape(route), escape(method), status, count);
+        }
+
+        out.push_str("# HELP rir_http_request_duration_seconds Latency of http requests.\n");
+        out.push_str("# TYPE rir_http_request_duration_seconds histogram\n");
+        for (&(ref route, ref method), histogram) in self.durations.lock().unwrap().iter() {
+            let labels = format!("route=\"{}\",method=\"{}\"", escape(route), escape(method));
+            for (count, upper) in histogram.buckets.iter().zip(BUCKETS.iter()) {
+                let _ = writeln!(out, "rir_http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}", labels, upper, count);
+            }
+            let _ = writeln!(out, "rir_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}", labels, histogram.count);
+            let _ = writeln!(out, "rir_http_request_duration_seconds_sum{{{}}} {}", labels, histogram.sum);
+            let _ = writeln!(out, "rir_http_request_duration_seconds_count{{{}}} {}", labels, histogram.count);
+        }
+
+        gauge(&mut out, "rir_http_requests_in_flight", "Requests currently being handled.", self.in_flight.load(Ordering::SeqCst));
+        gauge(&mut out, "rir_open_connections", "Currently open client connections.", self.connections.load(Ordering::SeqCst));
+        gauge(&mut out, "rir_worker_pool_queue_depth", "Requests waiting for a worker thread.", self.queued.load(Ordering::SeqCst));
+
+        let stats = self.static_file_cache.stats();
+        counter(&mut out, "rir_static_cache_hits_total", "Static files served from the cache.", stats.hits);
+        counter(&mut out, "rir_static_cache_misses_total", "Static files loaded from the file system.", stats.misses);
+        gauge(&mut out, "rir_static_cache_size_bytes", "Size of all cached static files.", stats.size);
+        gauge(&mut out, "rir_static_cache_entries", "Amount of cached static files.", stats.entries);
+        out
+    }
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: usize) {
+    let _ = write!(out, "# HELP {} {}\n# TYPE {} gauge\n{} {}\n", name, help, name, name, value);
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: usize) {
+    let _ = write!(out, "# HELP {} {}\n# TYPE {} counter\n{} {}\n", name, help, name, name, value);
+}
+
+fn method_label(method: &str) -> &str {
+    if METHODS.contains(&method) { method } else { OTHER_METHOD }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Counts an open connection as long as it is alive
+pub struct ConnectionGuard {
+    metrics: Arc<Metrics>,
+}
+
+impl ConnectionGuard {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        metrics.connections.fetch_add(1, Ordering::SeqCst);
+        ConnectionGuard { metrics }
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.metrics.connections.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Handler serving the rendered metrics
+pub struct MetricsHandler {
+    metrics: Arc<Metrics>,
+}
+
+impl MetricsHandler {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        MetricsHandler { metrics }
+    }
+}
+
+impl Handler for MetricsHandler {
+    fn handle(&self, _: &mut Request) -> Result<Response, HttpError> {
+        Response::builder()
+            .header(::http::header::CONTENT_TYPE, ::http::header::HeaderValue::from_static("text/plain; version=0.0.4"))
+            .body(self.metrics.render())
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_requests() {
+        let metrics = Arc::new(Metrics::new(Arc::new(StaticFileCache::new())));
+        metrics.request_started();
+        metrics.request_finished("/hello/:name", "GET", 200, Duration::from_millis(30));
+        metrics.observe(UNMATCHED_ROUTE, "GET", 404, Duration::from_millis(1));
+        let _connection = ConnectionGuard::new(metrics.clone());
+
+        let text = metrics.render();
+        assert!(text.contains("rir_http_requests_total{route=\"/hello/:name\",method=\"GET\",status=\"200\"} 1\n"), text);
+        assert!(text.contains("rir_http_request_duration_seconds_bucket{route=\"/hello/:name\",method=\"GET\",le=\"0.025\"} 0\n"), text);
+        assert!(text.contains("rir_http_request_duration_seconds_bucket{route=\"/hello/:name\",method=\"GET\",le=\"0.05\"} 1\n"), text);
+        assert!(text.contains("rir_http_requests_in_flight 0\n"), text);
+        assert!(text.contains("rir_open_connections 1\n"), text);
+    }
+
+    #[test]
+    fn bounded_method_labels() {
+        let metrics = Metrics::new(Arc::new(StaticFileCache::new()));
+        for method in &["FOO", "BAR", "get", "PATCH"] {
+            metrics.observe(UNMATCHED_ROUTE, method, 405, Duration::from_millis(1));
+        }
+
+        let text = metrics.render();
+        assert!(text.contains("rir_http_requests_total{route=\"<unmatched>\",method=\"OTHER\",status=\"405\"} 3\n"), text);
+        assert!(text.contains("rir_http_requests_total{route=\"<unmatched>\",method=\"PATCH\",status=\"405\"} 1\n"), text);
+        assert!(!text.contains("FOO"), text);
+    }
+}
@@ -0,0 +1,42 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Contains the middleware trait which is used to run code around the handler of a route.
+//! Middlewares can be registered on the router, a scope or a single route.
+
+use request::Request;
+use response::Response;
+use error::HttpError;
+
+/// Middleware trait
+///
+/// ```
+/// # use rest_in_rust::*;
+/// struct OnlyJson;
+///
+/// impl Middleware for OnlyJson {
+///     fn before(&self, req: &mut Request) -> Result<(), HttpError> {
+///         match req.header_str("accept") {
+///             Some("application/json") => Ok(()),
+///             _ => Err(HttpError::bad_request("Only json is supported")),
+///         }
+///     }
+/// }
+/// ```
+pub trait Middleware: Send + Sync + 'static {
+    /// Called before the handler.
+    /// Returning an error skips all following middlewares and the handler, the error is used as response.
+    fn before(&self, _req: &mut Request) -> Result<(), HttpError> {
+        Ok(())
+    }
+
+    /// Called after the handler with the final response, also for error responses.
+    /// Only called if ```before``` of this middleware was called.
+    fn after(&self, _req: &Request, _resp: &mut Response) {}
+}
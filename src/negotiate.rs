@@ -0,0 +1,466 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Content negotiation between the registered serialization formats.
+//!
+//! The format of a response is picked from the ```Accept``` header of the request, honoring q-values.
+//! Request bodies are deserialized by the format matching their ```Content-Type```, see ```Request::body_as```.
+//! Json and form-urlencoded are always available, MessagePack, CBOR and XML with the features ```msgpack```, ```cbor``` and ```xml```.
+//! Further formats can be registered by implementing ```Format```.
+//! Register the formats as server state, otherwise ```Formats::default()``` is used.
+//!
+//! ```
+//! # extern crate rest_in_rust;
+//! # #[macro_use] extern crate serde_derive;
+//! use rest_in_rust::*;
+//! use rest_in_rust::negotiate::Formats;
+//!
+//! #[derive(Serialize)]
+//! struct User {
+//!     name: String,
+//! }
+//!
+//! fn get_user(req: &mut Request) -> Result<Response, HttpError> {
+//!     Response::negotiated(req, &User { name: "admin".into() })
+//! }
+//!
+//! # fn main() {
+//! let mut r = Router::new();
+//! r.get("/user", get_user);
+//! let s = Server::new("127.0.0.1:8080".parse().unwrap(), r);
+//! s.add_state(Formats::default());
+//! # }
+//! ```
+
+use std::borrow::Cow;
+use std::sync::Arc;
+use http::header::{ACCEPT, CONTENT_TYPE, VARY, HeaderValue};
+use serde::{Serialize, Serializer};
+use serde::de::{DeserializeOwned, IgnoredAny};
+
+use error::HttpError;
+use request::Request;
+use response::Response;
+
+/// A serialization format like json
+pub trait Format: Send + Sync + 'static {
+    /// media type of the format like ```application/json```, used as ```Content-Type```
+    fn media_type(&self) -> &str;
+
+    fn serialize(&self, value: &::erased_serde::Serialize) -> Result<Vec<u8>, HttpError>;
+
+    /// creates a deserializer for the request body and passes it to ```deserialize_with```.
+    /// Formats which can't be used for request bodies keep the default, which answers with 415
+    fn deserialize(&self, body: &[u8], deserialize_with: &mut FnMut(&mut ::erased_serde::Deserializer) -> Result<(), ::erased_serde::Error>) -> Result<(), HttpError> {
+        let _ = (body, deserialize_with);
+        Err(HttpError::unsupported_media_type(format!("{} is not supported for request bodies", self.media_type())))
+    }
+}
+
+/// makes an erased value usable with serializers requiring a sized value
+struct Erased<'a>(&'a ::erased_serde::Serialize);
+
+impl<'a> Serialize for Erased<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ::erased_serde::serialize(self.0, serializer)
+    }
+}
+
+fn serialize_error<E: ::std::fmt::Display>(media_type: &str, e: E) -> HttpError {
+    error!("Could not serialize response as {}: {}", media_type, e);
+    HttpError::internal_server_error(format!("Could not serialize response as {}", media_type))
+}
+
+fn deserialize_error<E: ::std::fmt::Display>(media_type: &str, e: E) -> HttpError {
+    ::validate::syntax_error(media_type, e)
+}
+
+/// ```application/json```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json;
+
+impl Format for Json {
+    fn media_type(&self) -> &str {
+        "application/json"
+    }
+
+    fn serialize(&self, value: &::erased_serde::Serialize) -> Result<Vec<u8>, HttpError> {
+        ::serde_json::to_vec(&Erased(value)).map_err(|e| serialize_error(self.media_type(), e))
+    }
+
+    fn deserialize(&self, body: &[u8], deserialize_with: &mut FnMut(&mut ::erased_serde::Deserializer) -> Result<(), ::erased_serde::Error>) -> Result<(), HttpError> {
+        let mut de = ::serde_json::Deserializer::from_slice(body);
+        deserialize_with(&mut ::erased_serde::Deserializer::erase(&mut de)).map_err(|e| deserialize_error(self.media_type(), e))?;
+        de.end().map_err(|e| deserialize_error(self.media_type(), e))
+    }
+}
+
+/// ```application/x-www-form-urlencoded```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Form;
+
+impl Format for Form {
+    fn media_type(&self) -> &str {
+        "application/x-www-form-urlencoded"
+    }
+
+    fn serialize(&self, value: &::erased_serde::Serialize) -> Result<Vec<u8>, HttpError> {
+        ::serde_urlencoded::to_string(&Erased(value)).map(String::into_bytes).map_err(|e| serialize_error(self.media_type(), e))
+    }
+
+    fn deserialize(&self, body: &[u8], deserialize_with: &mut FnMut(&mut ::erased_serde::Deserializer) -> Result<(), ::erased_serde::Error>) -> Result<(), HttpError> {
+        let de = ::serde_urlencoded::Deserializer::new(::url::form_urlencoded::parse(body));
+        deserialize_with(&mut ::erased_serde::Deserializer::erase(de)).map_err(|e| deserialize_error(self.media_type(), e))
+    }
+}
+
+/// ```application/msgpack```
+#[cfg(feature = "msgpack")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MessagePack;
+
+#[cfg(feature = "msgpack")]
+impl Format for MessagePack {
+    fn media_type(&self) -> &str {
+        "application/msgpack"
+    }
+
+    fn serialize(&self, value: &::erased_serde::Serialize) -> Result<Vec<u8>, HttpError> {
+        ::rmp_serde::to_vec_named(&Erased(value)).map_err(|e| serialize_error(self.media_type(), e))
+    }
+
+    fn deserialize(&self, body: &[u8], deserialize_with: &mut FnMut(&mut ::erased_serde::Deserializer) -> Result<(), ::erased_serde::Error>) -> Result<(), HttpError> {
+        let mut de = ::rmp_serde::Deserializer::new(body);
+        deserialize_with(&mut ::erased_serde::Deserializer::erase(&mut de)).map_err(|e| deserialize_error(self.media_type(), e))
+    }
+}
+
+/// ```application/cbor```
+#[cfg(feature = "cbor")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Cbor;
+
+#[cfg(feature = "cbor")]
+impl Format for Cbor {
+    fn media_type(&self) -> &str {
+        "application/cbor"
+    }
+
+    fn serialize(&self, value: &::erased_serde::Serialize) -> Result<Vec<u8>, HttpError> {
+        ::serde_cbor::to_vec(&Erased(value)).map_err(|e| serialize_error(self.media_type(), e))
+    }
+
+    fn deserialize(&self, body: &[u8], deserialize_with: &mut FnMut(&mut ::erased_serde::Deserializer) -> Result<(), ::erased_serde::Error>) -> Result<(), HttpError> {
+        let mut de = ::serde_cbor::Deserializer::from_slice(body);
+        deserialize_with(&mut ::erased_serde::Deserializer::erase(&mut de)).map_err(|e| deserialize_error(self.media_type(), e))?;
+        de.end().map_err(|e| deserialize_error(self.media_type(), e))
+    }
+}
+
+/// ```application/xml```, the root element is named after the serialized type
+#[cfg(feature = "xml")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Xml;
+
+#[cfg(feature = "xml")]
+impl Format for Xml {
+    fn media_type(&self) -> &str {
+        "application/xml"
+    }
+
+    fn serialize(&self, value: &::erased_serde::Serialize) -> Result<Vec<u8>, HttpError> {
+        let mut body = Vec::new();
+        ::serde_xml_rs::serialize(Erased(value), &mut body).map_err(|e| serialize_error(self.media_type(), e))?;
+        Ok(body)
+    }
+
+    fn deserialize(&self, body: &[u8], deserialize_with: &mut FnMut(&mut ::erased_serde::Deserializer) -> Result<(), ::erased_serde::Error>) -> Result<(), HttpError> {
+        let mut de = ::serde_xml_rs::Deserializer::new_from_reader(body);
+        deserialize_with(&mut ::erased_serde::Deserializer::erase(&mut de)).map_err(|e| deserialize_error(self.media_type(), e))
+    }
+}
+
+/// Registered formats, the first one is preferred if the client accepts several formats equally
+#[derive(Clone)]
+pub struct Formats {
+    formats: Vec<Arc<Box<Format>>>,
+}
+
+impl Default for Formats {
+    /// json and the formats enabled via cargo features
+    fn default() -> Self {
+        let formats = Formats::new().register(Json).register(Form);
+        #[cfg(feature = "msgpack")]
+        let formats = formats.register(MessagePack);
+        #[cfg(feature = "cbor")]
+        let formats = formats.register(Cbor);
+        #[cfg(feature = "xml")]
+        let formats = formats.register(Xml);
+        formats
+    }
+}
+
+impl Formats {
+    /// no formats, every request is answered with 406
+    pub fn new() -> Self {
+        Formats { formats: Vec::new() }
+    }
+
+    pub fn register<F: Format>(mut self, format: F) -> Self {
+        self.formats.push(Arc::new(Box::new(format)));
+        self
+    }
+
+    /// selects the format with the highest q-value in the ```Accept``` header, the first format if the header is missing
+    pub fn negotiate(&self, req: &Request) -> Option<&Format> {
+        let accept: Vec<&str> = req.headers().get_all(ACCEPT).iter().filter_map(|v| v.to_str().ok()).collect();
+        if accept.is_empty() {
+            return self.formats.first().map(|f| &***f);
+        }
+        let ranges = MediaRange::parse_all(&accept.join(","));
+        let mut best: Option<(&Format, f32)> = None;
+        for format in self.formats.iter() {
+            let q = MediaRange::quality(&ranges, format.media_type());
+            if q > 0.0 && best.map(|(_, best_q)| q > best_q).unwrap_or(true) {
+                best = Some((&***format, q));
+            }
+        }
+        best.map(|(format, _)| format)
+    }
+
+    /// serializes the value in the negotiated format, returns 406 if the client accepts none of the formats
+    pub fn respond<T: Serialize>(&self, req: &Request, value: &T) -> Result<Response, HttpError> {
+        let format = match self.negotiate(req) {
+            Some(format) => format,
+            None => {
+                let accepted = self.formats.iter().map(|f| f.media_type()).collect::<Vec<_>>().join(", ");
+                let mut err = HttpError::not_acceptable(format!("Acceptable formats: {}", accepted));
+                err.headers.insert(VARY, HeaderValue::from_static("Accept"));
+                return Err(err);
+            }
+        };
+        let body = format.serialize(value)?;
+        Response::builder()
+            .header_str_value(CONTENT_TYPE, format.media_type())?
+            .header(VARY, HeaderValue::from_static("Accept"))
+            .body_vec(body)
+            .build()
+    }
+
+    /// deserializes the request body with the format matching the ```Content-Type```, returns 415 if no format matches.
+    /// Media types with a structured syntax suffix like ```application/vnd.api+json``` use the format of the suffix
+    pub fn deserialize<T: DeserializeOwned>(&self, req: &Request) -> Result<T, HttpError> {
+        let content_type = req.header(&CONTENT_TYPE).ok_or_else(|| HttpError::unsupported_media_type("Missing Content-Type"))?;
+        let (media_type, charset) = parse_content_type(content_type);
+        let format = self.find(&media_type).ok_or_else(|| HttpError::unsupported_media_type(format!("Unsupported Content-Type {}", media_type)))?;
+        let body = match *req.body().inner() {
+            Some(ref body) => body,
+            None => return Err(HttpError::bad_request("No body given")),
+        };
+        let body = decode_charset(body, charset.as_ref().map(String::as_str))?;
+
+        let mut value = None;
+        let mut failure = None;
+        let result = format.deserialize(&body, &mut |de: &mut ::erased_serde::Deserializer| {
+            match ::serde_path_to_error::deserialize::<_, T>(de) {
+                Ok(deserialized) => {
+                    value = Some(deserialized);
+                    Ok(())
+                }
+                Err(e) => {
+                    let path = e.path().to_string();
+                    let message = e.into_inner().to_string();
+                    let err = <::erased_serde::Error as ::serde::de::Error>::custom(&message);
+                    failure = Some((path, message));
+                    Err(err)
+                }
+            }
+        });
+        if let Some((path, message)) = failure {
+            // a body readable without a target type is well formed, so the error concerns the data
+            let well_formed = format.deserialize(&body, &mut |de: &mut ::erased_serde::Deserializer| {
+                ::erased_serde::deserialize::<IgnoredAny>(de).map(|_| ())
+            }).is_ok();
+            return Err(if well_formed {
+                ::validate::data_error(&media_type, &path, message)
+            } else {
+                ::validate::syntax_error(&media_type, message)
+            });
+        }
+        result?;
+        value.ok_or_else(|| HttpError::bad_request(format!("Could not parse body as {}", media_type)))
+    }
+
+    fn find(&self, media_type: &str) -> Option<&Format> {
+        let suffix = media_type.rfind('+').map(|i| format!("application/{}", &media_type[i + 1..]));
+        let find = |media_type: &str| self.formats.iter().find(|f| f.media_type().eq_ignore_ascii_case(media_type)).map(|f| &***f);
+        find(media_type).or_else(|| suffix.and_then(|suffix| find(&suffix)))
+    }
+}
+
+/// splits ```text/plain; charset=utf-8``` into the lowercase media type and charset
+fn parse_content_type(content_type: &str) -> (String, Option<String>) {
+    let mut parts = content_type.split(';');
+    let media_type = parts.next().unwrap_or("").trim().to_lowercase();
+    let charset = parts.filter_map(|param| {
+        let mut pair = param.splitn(2, '=');
+        match (pair.next(), pair.next()) {
+            (Some(name), Some(value)) if name.trim().eq_ignore_ascii_case("charset") => Some(value.trim().trim_matches('"').to_lowercase()),
+            _ => None,
+        }
+    }).next();
+    (media_type, charset)
+}
+
+/// converts the body to utf-8, other charsets than utf-8, us-ascii and iso-8859-1 are answered with 415
+fn decode_charset<'a>(body: &'a [u8], charset: Option<&str>) -> Result<Cow<'a, [u8]>, HttpError> {
+    match charset {
+        None | Some("utf-8") | Some("utf8") | Some("us-ascii") => Ok(Cow::Borrowed(body)),
+        Some("iso-8859-1") | Some("latin1") => Ok(Cow::Owned(body.iter().map(|b| *b as char).collect::<String>().into_bytes())),
+        Some(charset) => Err(HttpError::unsupported_media_type(format!("Unsupported charset {}", charset))),
+    }
+}
+
+/// one entry of the ```Accept``` header
+#[derive(Debug, PartialEq)]
+struct MediaRange {
+    media_type: String,
+    q: f32,
+}
+
+impl MediaRange {
+    fn parse_all(accept: &str) -> Vec<MediaRange> {
+        accept.split(',').filter_map(|range| {
+            let mut parts = range.split(';');
+            let media_type = parts.next().unwrap_or("").trim().to_lowercase();
+            if media_type.is_empty() {
+                return None;
+            }
+            let q = parts.filter_map(|param| {
+                let param = param.trim();
+                if param.starts_with("q=") { param[2..].parse::<f32>().ok() } else { None }
+            }).next().unwrap_or(1.0);
+            Some(MediaRange { media_type, q })
+        }).collect()
+    }
+
+    /// q-value of the most specific range matching the media type, 0 if none matches
+    fn quality(ranges: &[MediaRange], media_type: &str) -> f32 {
+        let media_type = media_type.to_lowercase();
+        let wildcard = format!("{}/*", media_type.split('/').next().unwrap_or(""));
+        let specificity = |range: &MediaRange| {
+            if range.media_type == media_type {
+                Some(3)
+            } else if range.media_type == wildcard {
+                Some(2)
+            } else if range.media_type == "*/*" {
+                Some(1)
+            } else {
+                None
+            }
+        };
+        ranges.iter()
+            .filter_map(|range| specificity(range).map(|s| (s, range.q)))
+            .max_by_key(|&(s, _)| s)
+            .map(|(_, q)| q)
+            .unwrap_or(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Text;
+
+    impl Format for Text {
+        fn media_type(&self) -> &str {
+            "text/plain"
+        }
+
+        fn serialize(&self, value: &::erased_serde::Serialize) -> Result<Vec<u8>, HttpError> {
+            Ok(format!("{}", ::serde_json::to_value(&Erased(value))?).into_bytes())
+        }
+    }
+
+    fn accepting(accept: &'static str) -> Request {
+        let mut req = ::http::Request::new(::body::Body(None));
+        req.headers_mut().insert(ACCEPT, HeaderValue::from_static(accept));
+        Request::from_http(req)
+    }
+
+    #[test]
+    fn negotiation() {
+        let formats = Formats::new().register(Json).register(Text);
+        let media_type = |accept| formats.negotiate(&accepting(accept)).map(|f| f.media_type().to_string());
+
+        assert_eq!(Some("application/json".to_string()), formats.negotiate(&Request::get("/").unwrap()).map(|f| f.media_type().to_string()));
+        assert_eq!(Some("text/plain".to_string()), media_type("text/plain"));
+        assert_eq!(Some("text/plain".to_string()), media_type("application/json;q=0.5, text/*"));
+        assert_eq!(Some("application/json".to_string()), media_type("*/*"));
+        assert_eq!(Some("application/json".to_string()), media_type("*/*;q=0.8, text/plain;q=0"));
+        assert_eq!(None, media_type("application/yaml"));
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct User {
+        name: String,
+    }
+
+    fn with_body(content_type: &'static str, body: &[u8]) -> Request {
+        let mut req = ::http::Request::new(::body::Body(Some(body.to_vec())));
+        req.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+        Request::from_http(req)
+    }
+
+    #[test]
+    fn deserialize_by_content_type() {
+        let formats = Formats::default();
+        let user = User { name: "jürgen".to_string() };
+        assert_eq!(user, formats.deserialize(&with_body("application/json; charset=UTF-8", "{\"name\": \"jürgen\"}".as_bytes())).unwrap());
+        assert_eq!(user, formats.deserialize(&with_body("application/vnd.user+json", "{\"name\": \"jürgen\"}".as_bytes())).unwrap());
+        assert_eq!(user, formats.deserialize(&with_body("application/x-www-form-urlencoded", b"name=j%C3%BCrgen")).unwrap());
+        assert_eq!(user, formats.deserialize(&with_body("application/json; charset=iso-8859-1", b"{\"name\": \"j\xfcrgen\"}")).unwrap());
+
+        let status = |req: Request| formats.deserialize::<User>(&req).unwrap_err().status.as_u16();
+        assert_eq!(415, status(with_body("application/yaml", b"name: admin")));
+        assert_eq!(415, status(with_body("application/json; charset=utf-16", b"{}")));
+        assert_eq!(415, status(Request::get("/").unwrap()));
+        assert_eq!(422, status(with_body("application/json", b"{\"name\": 1}")));
+        assert_eq!(422, status(with_body("application/json", b"{}")));
+        assert_eq!(400, status(with_body("application/json", b"{\"name\": ")));
+        assert_eq!(400, status(with_body("application/json", b"{\"name\": \"a\"} trailing")));
+    }
+
+    #[test]
+    fn respond() {
+        let formats = Formats::new().register(Json);
+        let response = formats.respond(&accepting("application/*"), &vec![1, 2]).unwrap();
+        assert_eq!("application/json", response.headers().get(CONTENT_TYPE).unwrap());
+        assert_eq!("Accept", response.headers().get(VARY).unwrap());
+        assert_eq!(Some(b"[1,2]".to_vec()), response.into_vec());
+
+        let err = formats.respond(&accepting("application/yaml"), &vec![1, 2]).unwrap_err();
+        assert_eq!(406, err.status.as_u16());
+    }
+
+    #[cfg(feature = "xml")]
+    #[test]
+    fn xml() {
+        let formats = Formats::default();
+        let user = User { name: "jürgen".to_string() };
+        assert_eq!(user, formats.deserialize(&with_body("application/xml", "<User><name>jürgen</name></User>".as_bytes())).unwrap());
+        assert_eq!(user, formats.deserialize(&with_body("application/vnd.user+xml", "<User><name>jürgen</name></User>".as_bytes())).unwrap());
+
+        let response = formats.respond(&accepting("application/xml"), &user).unwrap();
+        assert_eq!("application/xml", response.headers().get(CONTENT_TYPE).unwrap());
+        let body = String::from_utf8(response.into_vec().unwrap()).unwrap();
+        assert!(body.contains("<name>jürgen</name>"), body);
+    }
+}
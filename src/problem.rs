@@ -0,0 +1,138 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Machine readable errors as ```application/problem+json``` (RFC 7807).
+//!
+//! Errors are rendered as problem details if the client accepts ```application/problem+json```
+//! or if ```ProblemJson``` is registered as error renderer.
+//!
+//! ```
+//! # use rest_in_rust::*;
+//! use rest_in_rust::problem::ProblemJson;
+//!
+//! fn create_user(_: &mut Request) -> Result<Response, HttpError> {
+//!     Err(HttpError::bad_request("Invalid user")
+//!         .problem_type("https://example.com/problems/invalid-user")
+//!         .extension("fields", vec!["name"]))
+//! }
+//!
+//! let mut r = Router::new();
+//! r.error_renderer(StatusRange::all(), ProblemJson);
+//! r.post("/users", create_user);
+//! ```
+
+use http::header::{ACCEPT, CONTENT_TYPE, HeaderValue};
+use serde_json::{Map, Value};
+
+use error::HttpError;
+use request::Request;
+use response::Response;
+use router::ErrorRenderer;
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Optional problem details of an ```HttpError```, members which are not set are derived from the error
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProblemDetails {
+    /// uri identifying the problem type, ```about:blank``` if not set
+    pub type_uri: Option<String>,
+    /// short summary of the problem type, the reason phrase of the status if not set
+    pub title: Option<String>,
+    /// explanation of this occurrence, the message of the error if not set
+    pub detail: Option<String>,
+    /// uri identifying this occurrence
+    pub instance: Option<String>,
+    /// additional members like field errors
+    pub extensions: Map<String, Value>,
+}
+
+impl ProblemDetails {
+    /// the problem document of the error
+    pub fn to_json(err: &HttpError) -> Value {
+        let empty = ProblemDetails::default();
+        let problem = err.problem.as_ref().unwrap_or(&empty);
+        let mut document = problem.extensions.clone();
+        document.insert("type".to_string(), problem.type_uri.clone().unwrap_or_else(|| "about:blank".to_string()).into());
+        let title = problem.title.clone().or_else(|| err.status.canonical_reason().map(String::from));
+        if let Some(title) = title {
+            document.insert("title".to_string(), title.into());
+        }
+        document.insert("status".to_string(), err.status.as_u16().into());
+        document.insert("detail".to_string(), problem.detail.clone().unwrap_or_else(|| err.msg.clone()).into());
+        if let Some(ref instance) = problem.instance {
+            document.insert("instance".to_string(), instance.clone().into());
+        }
+        Value::Object(document)
+    }
+}
+
+/// Error renderer serializing every error as problem details
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProblemJson;
+
+impl ErrorRenderer for ProblemJson {
+    fn render(&self, _: &Request, err: &HttpError) -> Response {
+        let mut response = Response::from(ProblemDetails::to_json(err).to_string());
+        response.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+        response
+    }
+}
+
+/// true if the ```Accept``` header explicitly lists ```application/problem+json```
+pub(crate) fn accepts_problem_json(req: &Request) -> bool {
+    req.headers().get_all(ACCEPT).iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media_range| {
+            let mut parts = media_range.split(';');
+            let media_type = parts.next().unwrap_or("").trim();
+            let refused = parts.any(|param| {
+                let param = param.trim();
+                param.starts_with("q=") && param[2..].parse::<f32>().map(|q| q == 0.0).unwrap_or(false)
+            });
+            media_type.eq_ignore_ascii_case(PROBLEM_JSON) && !refused
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn problem_document() {
+        let err = HttpError::bad_request("Name is missing")
+            .problem_type("https://example.com/problems/validation")
+            .instance("/users/1")
+            .extension("fields", vec!["name"]);
+        let expected = json_value(r#"{"type": "https://example.com/problems/validation", "title": "Bad Request", "status": 400,
+            "detail": "Name is missing", "instance": "/users/1", "fields": ["name"]}"#);
+        assert_eq!(expected, ProblemDetails::to_json(&err));
+
+        let err = HttpError::not_found("No such user").title("Unknown user");
+        let expected = json_value(r#"{"type": "about:blank", "title": "Unknown user", "status": 404, "detail": "No such user"}"#);
+        assert_eq!(expected, ProblemDetails::to_json(&err));
+    }
+
+    #[test]
+    fn accept_header() {
+        assert!(!accepts_problem_json(&Request::get("/").unwrap()));
+        assert!(accepts_problem_json(&accepting("text/html, Application/Problem+JSON;q=0.9")));
+        assert!(!accepts_problem_json(&accepting("application/problem+json; q=0")));
+    }
+
+    fn accepting(accept: &'static str) -> Request {
+        let mut req = ::http::Request::new(::body::Body(None));
+        req.headers_mut().insert(ACCEPT, HeaderValue::from_static(accept));
+        Request::from_http(req)
+    }
+
+    fn json_value(json: &str) -> Value {
+        ::serde_json::from_str(json).unwrap()
+    }
+}
@@ -0,0 +1,305 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Token bucket rate limiting.
+//! The limiter is a middleware, register it on the router, a scope or a route.
+//! Throttled requests are answered with 429, ```Retry-After``` and the ```RateLimit-*``` headers.
+//!
+//! ```
+//! # use rest_in_rust::*;
+//! use std::time::Duration;
+//! # fn search(_: &mut Request) -> Result<Response, HttpError> {
+//! #     Ok("".into())
+//! # }
+//!
+//! let mut r = Router::new();
+//! r.middleware(RateLimiter::new(100, Duration::from_secs(60)));
+//! r.get("/search", search).middleware(RateLimiter::new(5, Duration::from_secs(1)).key(RateLimitKey::Principal));
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use http::header::{HeaderName, HeaderValue, HeaderMap};
+
+use error::HttpError;
+use middleware::Middleware;
+use request::Request;
+use response::Response;
+
+/// Defines which requests share a bucket
+pub enum RateLimitKey {
+    /// the client ip, see ```Request::client_ip```
+    ClientIp,
+    /// the name of the authenticated principal, unauthenticated requests are not limited
+    Principal,
+    /// custom key, returning ```None``` skips the limit for the request
+    Custom(Box<Fn(&Request) -> Option<String> + Send + Sync>),
+}
+
+impl RateLimitKey {
+    fn resolve(&self, req: &Request) -> Option<String> {
+        match *self {
+            RateLimitKey::ClientIp => req.client_ip().map(|ip| ip.to_string()),
+            RateLimitKey::Principal => req.principal().map(|p| p.name.clone()),
+            RateLimitKey::Custom(ref f) => f(req),
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+    /// position in ```Buckets::lru```
+    used: u64,
+}
+
+/// Buckets by key, with their keys ordered by last use so eviction doesn't scan the table
+#[derive(Default)]
+struct Buckets {
+    by_key: HashMap<String, Bucket>,
+    lru: BTreeMap<u64, String>,
+    clock: u64,
+}
+
+impl Buckets {
+    fn touch(&mut self, key: &str) {
+        let clock = &mut self.clock;
+        let lru = &mut self.lru;
+        if let Some(bucket) = self.by_key.get_mut(key) {
+            *clock += 1;
+            let name = lru.remove(&bucket.used).unwrap_or_else(|| key.to_string());
+            bucket.used = *clock;
+            lru.insert(*clock, name);
+        }
+    }
+
+    fn insert(&mut self, key: String, bucket: Bucket) {
+        self.clock += 1;
+        let bucket = Bucket { used: self.clock, ..bucket };
+        self.lru.insert(self.clock, key.clone());
+        self.by_key.insert(key, bucket);
+    }
+
+    /// the least recently used key
+    fn oldest(&self) -> Option<&String> {
+        self.lru.values().next()
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(bucket) = self.by_key.remove(key) {
+            self.lru.remove(&bucket.used);
+        }
+    }
+}
+
+/// Token bucket rate limiter
+pub struct RateLimiter {
+    capacity: u32,
+    period: Duration,
+    key: RateLimitKey,
+    max_keys: usize,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    /// allows ```requests``` per ```period``` for every key, bursts up to ```requests``` are allowed.
+    /// Requests are keyed by client ip and at most 10_000 keys are tracked.
+    pub fn new(requests: u32, period: Duration) -> Self {
+        RateLimiter {
+            capacity: ::std::cmp::max(requests, 1),
+            period,
+            key: RateLimitKey::ClientIp,
+            max_keys: 10_000,
+            buckets: Mutex::new(Buckets::default()),
+        }
+    }
+
+    /// sets how requests are grouped
+    pub fn key(mut self, key: RateLimitKey) -> Self {
+        self.key = key;
+        self
+    }
+
+    /// sets the maximum amount of tracked keys, if reached the least recently used bucket is dropped
+    pub fn max_keys(mut self, max_keys: usize) -> Self {
+        self.max_keys = ::std::cmp::max(max_keys, 1);
+        self
+    }
+
+    fn rate(&self) -> f64 {
+        let period = self.period.as_secs() as f64 + self.period.subsec_nanos() as f64 / 1_000_000_000.0;
+        self.capacity as f64 / period.max(0.001)
+    }
+
+    fn refill(&self, bucket: &mut Bucket, now: Instant) {
+        let elapsed = now.duration_since(bucket.last_refill);
+        let elapsed = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1_000_000_000.0;
+        bucket.tokens = (bucket.tokens + elapsed * self.rate()).min(self.capacity as f64);
+        bucket.last_refill = now;
+    }
+
+    /// takes a token for the key, returns the remaining tokens or the seconds to wait
+    fn acquire(&self, key: String, now: Instant) -> Result<f64, f64> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.by_key.contains_key(&key) {
+            buckets.touch(&key);
+        } else {
+            if buckets.by_key.len() >= self.max_keys {
+                self.evict(&mut buckets, now);
+            }
+            buckets.insert(key.clone(), Bucket { tokens: self.capacity as f64, last_refill: now, used: 0 });
+        }
+
+        let bucket = buckets.by_key.get_mut(&key).unwrap();
+        self.refill(bucket, now);
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(bucket.tokens)
+        } else {
+            Err((1.0 - bucket.tokens) / self.rate())
+        }
+    }
+
+    fn remaining(&self, key: &str, now: Instant) -> f64 {
+        let mut buckets = self.buckets.lock().unwrap();
+        match buckets.by_key.get_mut(key) {
+            Some(bucket) => {
+                self.refill(bucket, now);
+                bucket.tokens
+            }
+            None => self.capacity as f64,
+        }
+    }
+
+    /// drops the least recently used bucket and every following full bucket, they behave like new ones.
+    /// Each bucket is removed at most once, so the cost is amortised over the insertions.
+    fn evict(&self, buckets: &mut Buckets, now: Instant) {
+        if let Some(oldest) = buckets.oldest().cloned() {
+            trace!("Rate limiter full, dropping bucket {}", oldest);
+            buckets.remove(&oldest);
+        }
+        while let Some(oldest) = buckets.oldest().cloned() {
+            let full = {
+                let bucket = buckets.by_key.get_mut(&oldest).unwrap();
+                self.refill(bucket, now);
+                bucket.tokens >= self.capacity as f64
+            };
+            if !full {
+                break;
+            }
+            buckets.remove(&oldest);
+        }
+    }
+
+    fn add_headers(&self, headers: &mut HeaderMap<HeaderValue>, remaining: f64) {
+        let reset = ((self.capacity as f64 - remaining) / self.rate()).ceil() as u64;
+        headers.insert(HeaderName::from_static("ratelimit-limit"), HeaderValue::from(self.capacity as u64));
+        headers.insert(HeaderName::from_static("ratelimit-remaining"), HeaderValue::from(remaining.floor() as u64));
+        headers.insert(HeaderName::from_static("ratelimit-reset"), HeaderValue::from(reset));
+    }
+}
+
+impl Middleware for RateLimiter {
+    fn before(&self, req: &mut Request) -> Result<(), HttpError> {
+        let key = match self.key.resolve(req) {
+            Some(key) => key,
+            None => return Ok(()),
+        };
+        match self.acquire(key, Instant::now()) {
+            Ok(_) => Ok(()),
+            Err(wait) => {
+                let retry_after = wait.ceil() as u64;
+                debug!("Rate limit exceeded for {:?}, retry after {}s", req.client_ip(), retry_after);
+                let mut err = HttpError::too_many_requests("Too many requests");
+                self.add_headers(&mut err.headers, 0.0);
+                err.headers.insert(::http::header::RETRY_AFTER, HeaderValue::from(retry_after));
+                Err(err)
+            }
+        }
+    }
+
+    fn after(&self, req: &Request, resp: &mut Response) {
+        if resp.status() == ::http::StatusCode::TOO_MANY_REQUESTS {
+            return;
+        }
+        if let Some(key) = self.key.resolve(req) {
+            let remaining = self.remaining(&key, Instant::now());
+            self.add_headers(resp.headers_mut(), remaining);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_empties_and_refills() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(1));
+        let now = Instant::now();
+        assert!(limiter.acquire("a".into(), now).is_ok());
+        assert!(limiter.acquire("a".into(), now).is_ok());
+        let wait = limiter.acquire("a".into(), now).unwrap_err();
+        assert!(wait > 0.0 && wait <= 0.5);
+        assert!(limiter.acquire("b".into(), now).is_ok());
+
+        let later = now + Duration::from_millis(600);
+        assert!(limiter.acquire("a".into(), later).is_ok());
+    }
+
+    #[test]
+    fn bounded_keys() {
+        let limiter = RateLimiter::new(1, Duration::from_secs(60)).max_keys(2);
+        let now = Instant::now();
+        limiter.acquire("a".into(), now).unwrap();
+        limiter.acquire("b".into(), now + Duration::from_millis(1)).unwrap();
+        limiter.acquire("c".into(), now + Duration::from_millis(2)).unwrap();
+
+        {
+            let buckets = limiter.buckets.lock().unwrap();
+            assert_eq!(2, buckets.by_key.len());
+            assert_eq!(2, buckets.lru.len());
+            assert!(!buckets.by_key.contains_key("a"));
+        }
+
+        // a recently used key survives, the least recently used one is dropped
+        limiter.acquire("b".into(), now + Duration::from_millis(3)).unwrap_err();
+        limiter.acquire("d".into(), now + Duration::from_millis(4)).unwrap();
+        let buckets = limiter.buckets.lock().unwrap();
+        assert!(buckets.by_key.contains_key("b"));
+        assert!(!buckets.by_key.contains_key("c"));
+    }
+
+    #[test]
+    fn evicts_refilled_buckets() {
+        let limiter = RateLimiter::new(1, Duration::from_secs(1)).max_keys(3);
+        let now = Instant::now();
+        limiter.acquire("a".into(), now).unwrap();
+        limiter.acquire("b".into(), now).unwrap();
+        limiter.acquire("c".into(), now + Duration::from_millis(900)).unwrap();
+        limiter.acquire("d".into(), now + Duration::from_millis(1500)).unwrap();
+
+        let buckets = limiter.buckets.lock().unwrap();
+        let keys: Vec<&str> = buckets.lru.values().map(|k| k.as_str()).collect();
+        assert_eq!(vec!["c", "d"], keys);
+    }
+
+    #[test]
+    fn too_many_requests_response() {
+        let limiter = RateLimiter::new(1, Duration::from_secs(10)).key(RateLimitKey::Custom(Box::new(|_| Some("all".into()))));
+        let mut req = Request::get("/").unwrap();
+        limiter.before(&mut req).unwrap();
+        let err = limiter.before(&mut req).unwrap_err();
+
+        assert_eq!(429, err.status.as_u16());
+        assert_eq!("10", err.headers.get(::http::header::RETRY_AFTER).unwrap());
+        assert_eq!("0", err.headers.get("ratelimit-remaining").unwrap());
+    }
+}
@@ -0,0 +1,69 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Request ids used to correlate log lines of a single request.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use http::header::HeaderMap;
+
+/// name of the header an incoming request id is read from and echoed in
+pub const REQUEST_ID_HEADER: &'static str = "x-request-id";
+
+const MAX_LENGTH: usize = 200;
+
+static COUNTER: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// returns the id of the ```X-Request-Id``` header if it is valid, otherwise a new one
+pub fn from_headers(headers: &HeaderMap) -> String {
+    headers.get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| is_valid(id))
+        .map(|id| id.to_string())
+        .unwrap_or_else(generate)
+}
+
+/// generates a new id, unique within this process and unlikely to collide with other processes
+pub fn generate() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(::std::process::id());
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{:016x}-{:08x}", hasher.finish(), count as u32)
+}
+
+/// ids are limited to 200 printable ascii characters without spaces,
+/// so they can be used in log lines without escaping
+pub fn is_valid(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_LENGTH && id.bytes().all(|b| b > b' ' && b < 0x7F && b != b'"' && b != b'\\')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header::HeaderValue;
+
+    #[test]
+    fn incoming_id() {
+        let mut headers = HeaderMap::new();
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_static("abc-123"));
+        assert_eq!("abc-123", from_headers(&headers));
+
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_static("with space"));
+        assert_ne!("with space", from_headers(&headers));
+    }
+
+    #[test]
+    fn generated_ids_differ() {
+        let a = generate();
+        let b = generate();
+        assert_ne!(a, b);
+        assert!(is_valid(&a));
+    }
+}
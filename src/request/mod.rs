@@ -14,8 +14,13 @@ use ::body::Body;
 use std::sync::Arc;
 
 mod params;
+pub mod id;
 
 use error::HttpError;
+use auth::Principal;
+use server::proxy::ForwardedInfo;
+use server::unix::PeerCredentials;
+use std::net::{SocketAddr, IpAddr};
 pub use self::params::Params;
 
 /// Request wrapping a ```http::Request<Body>```
@@ -26,7 +31,12 @@ pub struct Request {
     state: StateHolder,
     params: Params,
     query: HashMap<String, Vec<String>>,
-    remote_addr: Option<::std::net::SocketAddr>,
+    remote_addr: Option<SocketAddr>,
+    forwarded: Option<ForwardedInfo>,
+    principal: Option<Principal>,
+    peer_credentials: Option<PeerCredentials>,
+    peer_certificate: Option<String>,
+    id: String,
 }
 
 enum StateHolder {
@@ -52,6 +62,11 @@ impl Default for Request {
             params: Params::default(),
             query: HashMap::default(),
             remote_addr: None,
+            forwarded: None,
+            principal: None,
+            peer_credentials: None,
+            peer_certificate: None,
+            id: id::generate(),
         }
     }
 }
@@ -60,7 +75,8 @@ impl Request {
     /// Creates a new request during parsing time
     pub fn new(req: HttpRequest<Body>, state: Arc<Container>, params: Params) -> Self {
         let query = Request::parse_query(req.uri().query());
-        Request { inner: req, params, state: StateHolder::Some(state), query, remote_addr: None }
+        let id = id::from_headers(req.headers());
+        Request { inner: req, params, state: StateHolder::Some(state), query, remote_addr: None, forwarded: None, principal: None, peer_credentials: None, peer_certificate: None, id }
     }
 
     /// returns a path parameter with the given name
@@ -164,6 +180,24 @@ impl Request {
         }
     }
 
+    /// deserializes the body with the format matching the ```Content-Type``` header, answers 415 for unsupported media types
+    /// and 400 if the body can't be parsed. Uses the ```negotiate::Formats``` registered as server state or the default formats
+    pub fn body_as<T: ::serde::de::DeserializeOwned>(&self) -> Result<T, HttpError> {
+        use ::negotiate::Formats;
+
+        match self.get_state::<Formats>() {
+            Some(formats) => formats.deserialize(self),
+            None => Formats::default().deserialize(self),
+        }
+    }
+
+    /// deserializes the body like ```body_as``` and validates it, invalid fields are answered with 422
+    pub fn valid_body<T: ::serde::de::DeserializeOwned + ::validate::Validate>(&self) -> Result<T, HttpError> {
+        let value: T = self.body_as()?;
+        value.validate()?;
+        Ok(value)
+    }
+
     /// sets a reference to the global state container
     pub fn set_state<T: Send + Sync + 'static>(&mut self, state: Arc<Container>) {
         self.state = StateHolder::Some(state);
@@ -206,6 +240,103 @@ impl Request {
         self.header(&hname)
     }
 
+    /// returns the address of the connection peer, this might be a proxy
+    pub fn remote_addr(&self) -> Option<SocketAddr> {
+        self.remote_addr
+    }
+
+    /// sets the address of the connection peer
+    pub fn set_remote_addr(&mut self, addr: SocketAddr) {
+        self.remote_addr = Some(addr);
+    }
+
+    /// returns the credentials of the peer process if the request was received via a unix domain socket
+    pub fn peer_credentials(&self) -> Option<&PeerCredentials> {
+        self.peer_credentials.as_ref()
+    }
+
+    /// sets the credentials of the peer process
+    pub fn set_peer_credentials(&mut self, credentials: PeerCredentials) {
+        self.peer_credentials = Some(credentials);
+    }
+
+    /// returns the subject of the verified client certificate, eg. ```CN=client,O=Example```
+    /// see ```TlsConfig::client_auth```
+    pub fn peer_certificate(&self) -> Option<&str> {
+        self.peer_certificate.as_ref().map(|s| s.as_str())
+    }
+
+    /// sets the subject of the verified client certificate
+    pub fn set_peer_certificate(&mut self, subject: String) {
+        self.peer_certificate = Some(subject);
+    }
+
+    /// returns the ip of the client, resolved via forwarding headers if the peer is a trusted proxy
+    /// see ```Server::set_trusted_proxies```
+    pub fn client_ip(&self) -> Option<IpAddr> {
+        match self.forwarded {
+            Some(ref forwarded) => Some(forwarded.client_ip),
+            None => self.remote_addr.map(|a| a.ip()),
+        }
+    }
+
+    /// returns the scheme the client used, resolved via forwarding headers if the peer is a trusted proxy
+    pub fn scheme(&self) -> &str {
+        if let Some(&ForwardedInfo { scheme: Some(ref scheme), .. }) = self.forwarded.as_ref() {
+            return scheme.as_str();
+        }
+        self.inner.uri().scheme_part().map(|s| s.as_str()).unwrap_or("http")
+    }
+
+    /// returns the host the client requested, resolved via forwarding headers if the peer is a trusted proxy
+    pub fn host(&self) -> Option<&str> {
+        if let Some(&ForwardedInfo { host: Some(ref host), .. }) = self.forwarded.as_ref() {
+            return Some(host.as_str());
+        }
+        self.header(&::http::header::HOST).or_else(|| self.inner.uri().host())
+    }
+
+    /// sets the client information forwarded by a trusted proxy
+    pub fn set_forwarded(&mut self, forwarded: ForwardedInfo) {
+        self.forwarded = Some(forwarded);
+    }
+
+    /// returns the principal that was resolved by an authentication middleware
+    /// eg. ```BasicAuth``` or ```BearerAuth```
+    pub fn principal(&self) -> Option<&Principal> {
+        self.principal.as_ref()
+    }
+
+    /// sets the authenticated principal of this request
+    pub fn set_principal(&mut self, principal: Principal) {
+        self.principal = Some(principal);
+    }
+
+    /// returns the id of this request, taken from a valid ```X-Request-Id``` header or generated.
+    /// The id is echoed in the ```X-Request-Id``` response header and part of the access and error logs.
+    pub fn id(&self) -> &str {
+        self.id.as_str()
+    }
+
+    /// headers to add to outbound requests to propagate the request id
+    ///
+    /// ```
+    /// # use rest_in_rust::*;
+    /// # #[allow(dead_code)]
+    /// fn call_backend(req: &mut Request) -> Result<Response, HttpError> {
+    ///     let headers = req.propagation_headers();
+    ///     assert_eq!(req.id(), headers.get("x-request-id").unwrap());
+    ///     Ok("".into())
+    /// }
+    /// ```
+    pub fn propagation_headers(&self) -> ::http::header::HeaderMap {
+        let mut headers = ::http::header::HeaderMap::new();
+        if let Ok(value) = ::http::header::HeaderValue::from_str(&self.id) {
+            headers.insert(id::REQUEST_ID_HEADER, value);
+        }
+        headers
+    }
+
     /// modify params
     pub fn params_mut(&mut self) -> &mut Params {
         &mut self.params
@@ -232,8 +363,19 @@ impl Request {
             params: Params::default(),
             query: HashMap::new(),
             remote_addr: None,
+            forwarded: None,
+            principal: None,
+            peer_credentials: None,
+            peer_certificate: None,
+            id: id::generate(),
         })
     }
+
+    /// wraps a request built in a test, without state and params
+    #[cfg(test)]
+    pub(crate) fn from_http(req: HttpRequest<Body>) -> Self {
+        Request::new(req, Arc::new(Container::new()), Params::new())
+    }
 }
 
 impl Deref for Request{
@@ -263,4 +405,13 @@ mod tests {
         assert_eq!(None, req.query_first("ne"));
         assert_eq!(2, req.query("hallo").unwrap().len());
     }
+
+    #[test]
+    fn request_id_from_header() {
+        let mut r = HttpRequest::new(::body::Body(None));
+        r.headers_mut().insert("x-request-id", ::http::header::HeaderValue::from_static("req-42"));
+        let req = Request::from_http(r);
+        assert_eq!("req-42", req.id());
+        assert_eq!("req-42", req.propagation_headers().get("x-request-id").unwrap());
+    }
 }
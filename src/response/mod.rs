@@ -56,6 +56,17 @@ impl Response {
         Ok(serialized.into())
     }
 
+    /// serializes the value in the format negotiated via the ```Accept``` header of the request.
+    /// Uses the ```negotiate::Formats``` registered as server state or the default formats
+    pub fn negotiated<T: ::serde::Serialize>(req: &::request::Request, value: &T) -> Result<Self, ::error::HttpError> {
+        use ::negotiate::Formats;
+
+        match req.get_state::<Formats>() {
+            Some(formats) => formats.respond(req, value),
+            None => Formats::default().respond(req, value),
+        }
+    }
+
     /// shortcut for creating a moved permanently response
     pub fn moved_permanent<T: AsRef<str>>(url: T) -> Result<Response, ::error::HttpError> {
         let value: HeaderValue = HeaderValue::from_str(url.as_ref())?;
@@ -73,6 +84,10 @@ impl Response {
         self.inner.headers()
     }
 
+    pub fn headers_mut(&mut self) -> &mut HeaderMap<HeaderValue> {
+        self.inner.headers_mut()
+    }
+
     pub fn body(&self) -> &Body {
         self.inner.body()
     }
@@ -0,0 +1,89 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use http::HeaderMap;
+use http::header::{HeaderValue, CONTENT_TYPE};
+
+/// Request limits of a single route, enforced by the http parser before the body is buffered.
+/// Unset limits fall back to the ```HttpCodecCfg``` of the server
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RouteLimits {
+    /// maximum body size in bytes, may be larger than the server wide limit
+    pub max_body_size: Option<usize>,
+    /// maximum number of headers, can only lower the server wide limit
+    pub max_headers: Option<usize>,
+    /// maximum length of the request line and headers in bytes, can only lower the server wide limit
+    pub max_header_len: Option<usize>,
+    /// media types like ```application/json``` accepted for request bodies, empty accepts all
+    pub content_types: Vec<String>,
+}
+
+/// Reason a request was rejected by the limits of its route
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LimitViolation {
+    Body,
+    Header,
+    ContentType,
+}
+
+impl RouteLimits {
+    /// checks a parsed request head, the body length is taken from the ```Content-Length``` header
+    pub(crate) fn check(&self, header_len: usize, headers: &HeaderMap<HeaderValue>, body_length: usize) -> Result<(), LimitViolation> {
+        if self.max_body_size.map(|max| body_length > max).unwrap_or(false) {
+            return Err(LimitViolation::Body);
+        }
+        let too_many_headers = self.max_headers.map(|max| headers.len() > max).unwrap_or(false);
+        if too_many_headers || self.max_header_len.map(|max| header_len > max).unwrap_or(false) {
+            return Err(LimitViolation::Header);
+        }
+        if body_length > 0 && !self.content_type_allowed(headers) {
+            return Err(LimitViolation::ContentType);
+        }
+        Ok(())
+    }
+
+    fn content_type_allowed(&self, headers: &HeaderMap<HeaderValue>) -> bool {
+        if self.content_types.is_empty() {
+            return true;
+        }
+        let media_type = headers.get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.split(';').next().unwrap_or("").trim());
+        match media_type {
+            Some(media_type) => self.content_types.iter().any(|allowed| allowed.trim().eq_ignore_ascii_case(media_type)),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(content_type: &str) -> HeaderMap<HeaderValue> {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_str(content_type).unwrap());
+        headers
+    }
+
+    #[test]
+    fn limits() {
+        let limits = RouteLimits { max_body_size: Some(10), max_headers: Some(1), max_header_len: Some(100), content_types: vec!["application/json".to_string()] };
+        assert_eq!(Ok(()), limits.check(50, &headers("Application/JSON; charset=utf-8"), 10));
+        assert_eq!(Err(LimitViolation::Body), limits.check(50, &headers("application/json"), 11));
+        assert_eq!(Err(LimitViolation::Header), limits.check(101, &headers("application/json"), 10));
+        assert_eq!(Err(LimitViolation::ContentType), limits.check(50, &headers("text/plain"), 10));
+        assert_eq!(Err(LimitViolation::ContentType), limits.check(50, &HeaderMap::new(), 10));
+        assert_eq!(Ok(()), limits.check(50, &HeaderMap::new(), 0));
+        assert_eq!(Ok(()), RouteLimits::default().check(50_000, &headers("text/plain"), 50_000_000));
+
+        let limits = RouteLimits { content_types: vec!["application/JSON".to_string()], ..RouteLimits::default() };
+        assert_eq!(Ok(()), limits.check(50, &headers("application/json"), 10));
+    }
+}
@@ -11,46 +11,127 @@
 //! Alsos contains routes and static file handler + cache.
 
 use http::Method;
-use handler::Handler;
+use handler::{Handler, HandlerPanic, PanicHook, panic_message};
+use error::HttpError;
+use middleware::Middleware;
+use cors::{Cors, PreflightHandler};
+use metrics::{Metrics, MetricsHandler};
+use request::Request;
+use response::Response;
 use route_recognizer::Router as Recognizer;
 use route_recognizer::Params;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::PathBuf;
 use self::staticfile::StaticFileCache;
 
-
-mod staticfile;
+pub(crate) mod staticfile;
+mod limits;
+mod renderer;
 
 pub use self::staticfile::{ChangeDetection, EvictionPolicy};
+pub use self::limits::RouteLimits;
+pub(crate) use self::limits::LimitViolation;
+pub use self::renderer::{ErrorRenderer, StatusRange};
+use self::renderer::{Renderers, render_error};
 
 /// Basic struct containing route registrations
 /// when creating a server out of this, it will be converted to InternalRouter 
 pub struct Router {
     static_file_cache: Arc<StaticFileCache>,
     intial: Vec<Route>,
+    middlewares: Vec<Arc<Box<Middleware>>>,
+    cors: Option<CorsConfig>,
+    metrics_path: Option<String>,
+    panic_hook: Option<PanicHook>,
+    error_renderers: Renderers,
+    fallback: Option<Route>,
+    /// fallbacks of scopes with their path prefix
+    scoped_fallbacks: Vec<(String, Route)>,
+}
+
+/// CORS of a router or scope, the middleware is shared by all of its routes
+#[derive(Clone)]
+struct CorsConfig {
+    cors: Arc<Cors>,
+    middleware: Arc<Box<Middleware>>,
 }
 
 /// internal router representation used by RestInRust, modifcations are no longer possible
 pub struct InternalRouter {
     //    static_file_cache: Arc<StaticFileCache>,
     routes: HashMap<Method, Recognizer<Arc<Route>>>,
+    metrics: Option<Arc<Metrics>>,
+    error_renderers: Renderers,
+    fallback: Option<Arc<Route>>,
+    /// sorted by descending prefix length, so the innermost scope wins
+    scoped_fallbacks: Vec<(String, Arc<Route>)>,
 }
 
 impl InternalRouter {
     pub fn new(router: Router) -> Self {
-        let mut r = InternalRouter { routes: HashMap::new() };// static_file_cache: router.static_file_cache
+        let mut r = InternalRouter { routes: HashMap::new(), metrics: None, error_renderers: router.error_renderers.clone(), fallback: None, scoped_fallbacks: Vec::new() };// static_file_cache: router.static_file_cache
+        let mut router = router;
+
+        if let Some(path) = router.metrics_path.take() {
+            let metrics = Arc::new(Metrics::new(router.static_file_cache.clone()));
+            router.intial.push(Route::new(Method::GET, path, MetricsHandler::new(metrics.clone())));
+            r.metrics = Some(metrics);
+        }
 
-        for route in router.intial.into_iter() {
+        // routes of a scope with own CORS keep it, all others use the CORS of the router
+        if let Some(ref cors) = router.cors {
+            let scoped_fallbacks = router.scoped_fallbacks.iter_mut().map(|&mut (_, ref mut route)| route);
+            for route in router.intial.iter_mut().chain(router.fallback.iter_mut()).chain(scoped_fallbacks).filter(|r| r.cors.is_none()) {
+                route.cors = Some(cors.clone());
+            }
+        }
+        let preflights: Vec<Route> = {
+            let with_options: Vec<&str> = router.intial.iter().filter(|r| r.method == Method::OPTIONS).map(|r| r.path.as_str()).collect();
+            let mut paths: Vec<(&str, &CorsConfig)> = router.intial.iter()
+                .filter(|r| !with_options.contains(&r.path.as_str()))
+                .filter_map(|r| r.cors.as_ref().map(|cors| (r.path.as_str(), cors)))
+                .collect();
+            paths.sort_by(|a, b| a.0.cmp(b.0));
+            paths.dedup_by(|a, b| a.0 == b.0);
+            paths.into_iter().map(|(path, cors)| {
+                let mut route = Route::new(Method::OPTIONS, path.to_string(), PreflightHandler::new(cors.cors.clone()));
+                route.preflight = true;
+                route
+            }).collect()
+        };
+        router.intial.extend(preflights);
+
+        let Router { intial, middlewares, panic_hook, error_renderers, fallback, scoped_fallbacks, .. } = router;
+        let prepare = |mut route: Route| {
+            if route.panic_hook.is_none() {
+                route.panic_hook = panic_hook.clone();
+            }
+            route.error_renderers.extend(error_renderers.iter().cloned());
+            if !route.preflight {
+                let mut route_middlewares: Vec<Arc<Box<Middleware>>> = route.cors.iter().map(|cors| cors.middleware.clone()).collect();
+                route_middlewares.extend(middlewares.iter().cloned());
+                route_middlewares.extend(route.middlewares.drain(..));
+                route.middlewares = route_middlewares;
+            }
+            Arc::new(route)
+        };
+        r.fallback = fallback.map(&prepare);
+        r.scoped_fallbacks = scoped_fallbacks.into_iter().map(|(prefix, route)| (prefix, prepare(route))).collect();
+        r.scoped_fallbacks.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+        for route in intial.into_iter() {
             let method = route.method.clone();
             let path = route.path.clone();
-            r.routes.entry(method).or_insert(Recognizer::new()).add(path.as_ref(), Arc::new(route));
+            r.routes.entry(method).or_insert(Recognizer::new()).add(path.as_ref(), prepare(route));
         }
         r
     }
 
+    /// returns the matching route, or the fallback route if none matches
     pub fn resolve<S: AsRef<str>>(&self, method: &Method, path: S) -> Option<(Arc<Route>, Params)> {
-        if let Some(found) = self.routes.get(method) {
+        let found = if let Some(found) = self.routes.get(method) {
             match found.recognize(path.as_ref()) {
                 Ok(matching) => {
                     Some((matching.handler.clone(), matching.params))
@@ -62,14 +143,144 @@ impl InternalRouter {
             }
         } else {
             None
-        }
+        };
+        found.or_else(|| {
+            let path = path.as_ref();
+            self.scoped_fallbacks.iter()
+                .find(|&&(ref prefix, _)| path == prefix || path.starts_with(&format!("{}/", prefix)))
+                .map(|&(_, ref fallback)| fallback)
+                .or(self.fallback.as_ref())
+                .map(|fallback| (fallback.clone(), Params::new()))
+        })
+    }
+
+    /// renders an error which did not occur in a route, eg. for requests rejected by the http parser
+    pub fn render_error(&self, req: &Request, err: HttpError) -> Response {
+        render_error(&self.error_renderers, req, err)
+    }
+
+    /// returns the metrics if they are enabled via ```Router::metrics```
+    pub fn metrics(&self) -> Option<&Arc<Metrics>> {
+        self.metrics.as_ref()
     }
 }
 
 impl Router {
     /// creates a new empty router
     pub fn new() -> Self {
-        Router { intial: Vec::new(), static_file_cache: Arc::new(StaticFileCache::new()) }
+        Router { intial: Vec::new(), static_file_cache: Arc::new(StaticFileCache::new()), middlewares: Vec::new(), cors: None, metrics_path: None, panic_hook: None, error_renderers: Vec::new(), fallback: None, scoped_fallbacks: Vec::new() }
+    }
+
+    /// enables CORS for all routes of this router, calling it again replaces the configuration.
+    /// Preflight requests are answered for every path without an own OPTIONS route
+    /// and the CORS headers are added to all responses, including error responses.
+    /// The CORS of a scope replaces the CORS of the router for the routes of the scope.
+    pub fn cors(&mut self, cors: Cors) -> &mut Self {
+        let middleware: Arc<Box<Middleware>> = Arc::new(Box::new(cors.clone()));
+        self.cors = Some(CorsConfig { cors: Arc::new(cors), middleware });
+        self
+    }
+
+    /// enables the built in metrics and serves them in the prometheus text format at the given path.
+    /// Router middlewares also apply to this route, so it can be protected by an auth guard.
+    pub fn metrics<P: Into<String>>(&mut self, path: P) -> &mut Self {
+        self.metrics_path = Some(path.into());
+        self
+    }
+
+    /// called whenever a handler or middleware panics, eg. to report the panic to an error tracker.
+    /// Panics are always logged and answered with a 500 response, the connection stays usable.
+    /// The hook of a scope replaces the hook of the router for the routes of the scope
+    pub fn panic_hook<F>(&mut self, hook: F) -> &mut Self
+        where F: Fn(&HandlerPanic) + Send + Sync + 'static {
+        self.panic_hook = Some(Arc::new(Box::new(hook)));
+        self
+    }
+
+    /// renders errors with the given status codes, eg. as html page or json document.
+    /// Renderers of a scope take precedence, within a router the first registered renderer matching the status is used.
+    /// Router renderers are also used for requests without a matching route and requests rejected by the http parser.
+    ///
+    /// ```
+    /// # use rest_in_rust::*;
+    /// let mut r = Router::new();
+    /// r.error_renderer(StatusRange::client_errors(), |_: &Request, err: &HttpError| {
+    ///     Response::from(format!("<html><body><h1>{}</h1></body></html>", err.msg))
+    /// });
+    /// ```
+    pub fn error_renderer<S: Into<StatusRange>, R: ErrorRenderer>(&mut self, status: S, renderer: R) -> &mut Self {
+        self.error_renderers.push((status.into(), Arc::new(Box::new(renderer))));
+        self
+    }
+
+    /// handles all requests without a matching route regardless of their method, instead of answering with 404.
+    /// Router middlewares and error renderers apply to the fallback route.
+    /// The fallback of a scope handles the unmatched requests below the prefix of the scope
+    pub fn fallback<H: Handler>(&mut self, h: H) -> &mut Route {
+        self.fallback = Some(Route::new(Method::GET, "*".to_string(), h));
+        self.fallback.as_mut().unwrap()
+    }
+
+    /// adds a middleware which is executed for every route of this router.
+    /// Router middlewares are executed before the middlewares of a scope or route.
+    pub fn middleware<M: Middleware>(&mut self, middleware: M) -> &mut Self {
+        self.middlewares.push(Arc::new(Box::new(middleware)));
+        self
+    }
+
+    /// registers all routes configured in the given closure below the path prefix.
+    /// Middlewares added to the scoped router only apply to the routes of this scope.
+    ///
+    /// ```
+    /// # use rest_in_rust::*;
+    /// # fn list_users(_: &mut Request) -> Result<Response, HttpError> {
+    /// #     Ok("".into())
+    /// # }
+    /// let mut r = Router::new();
+    /// r.scope("/admin", |admin| {
+    ///     admin.middleware(BearerAuth::new("admin", |token: &str| {
+    ///         if token == "secret" { Some(Principal::new("admin", AuthScheme::Bearer)) } else { None }
+    ///     }));
+    ///     admin.get("/users", list_users);
+    /// });
+    /// ```
+    pub fn scope<P, F>(&mut self, prefix: P, configure: F) -> &mut Self
+        where P: AsRef<str>, F: FnOnce(&mut Router) {
+        let mut scoped = Router { intial: Vec::new(), static_file_cache: self.static_file_cache.clone(), middlewares: Vec::new(), cors: None, metrics_path: None, panic_hook: None, error_renderers: Vec::new(), fallback: None, scoped_fallbacks: Vec::new() };
+        configure(&mut scoped);
+
+        let prefix = prefix.as_ref().trim_right_matches('/');
+        let Router { intial, middlewares, cors, panic_hook, error_renderers, fallback, scoped_fallbacks, .. } = scoped;
+        let apply_scope = |route: &mut Route| {
+            let mut route_middlewares = middlewares.clone();
+            route_middlewares.extend(route.middlewares.drain(..));
+            route.middlewares = route_middlewares;
+            route.error_renderers.extend(error_renderers.iter().cloned());
+            if route.cors.is_none() {
+                route.cors = cors.clone();
+            }
+            if route.panic_hook.is_none() {
+                route.panic_hook = panic_hook.clone();
+            }
+        };
+        for mut route in intial.into_iter() {
+            route.path = if route.path.starts_with('/') {
+                format!("{}{}", prefix, route.path)
+            } else {
+                format!("{}/{}", prefix, route.path)
+            };
+            apply_scope(&mut route);
+            self.intial.push(route);
+        }
+        for (inner_prefix, mut route) in scoped_fallbacks.into_iter() {
+            apply_scope(&mut route);
+            self.scoped_fallbacks.push((format!("{}{}", prefix, inner_prefix), route));
+        }
+        if let Some(mut route) = fallback {
+            apply_scope(&mut route);
+            self.scoped_fallbacks.push((prefix.to_string(), route));
+        }
+        self
     }
 
     /// configures the static file cache size in bytes.
@@ -81,17 +292,7 @@ impl Router {
     /// adds a new route to the router, please note the shortcut methods below.
     /// Additionally it returns a mutable reference to the Route which you can use to further modify the route 
     pub fn add<P: Into<String> + Sized + AsRef<str>, H: Handler>(&mut self, method: Method, path: P, h: H) -> &mut Route {
-        let path = path.into();
-        let route = Route {
-            path: path.clone(),
-            callback: Arc::new(Box::new(h)),
-            method: method.clone(),
-            threading: match method {
-                Method::GET | Method::OPTIONS => Threading::SAME,
-                _ => Threading::SEPERATE,
-            },
-        };
-
+        let route = Route::new(method, path.into(), h);
 
         self.intial.push(route);
         let index = self.intial.len() - 1;
@@ -181,6 +382,13 @@ pub struct Route {
     /// Defines if a route is processed in the same thread as the connection handling is done
     /// or in a pooled thread
     pub threading: Threading,
+    /// Middlewares executed around the callback, router and scope middlewares come first
+    pub middlewares: Vec<Arc<Box<Middleware>>>,
+    limits: RouteLimits,
+    panic_hook: Option<PanicHook>,
+    error_renderers: Renderers,
+    cors: Option<CorsConfig>,
+    preflight: bool,
 }
 
 /// Defines if a route is processed in the same thread as the connection handling is done
@@ -193,6 +401,24 @@ pub enum Threading {
 }
 
 impl Route {
+    fn new<H: Handler>(method: Method, path: String, h: H) -> Self {
+        Route {
+            path,
+            callback: Arc::new(Box::new(h)),
+            threading: match method {
+                Method::GET | Method::OPTIONS => Threading::SAME,
+                _ => Threading::SEPERATE,
+            },
+            method,
+            middlewares: Vec::new(),
+            limits: RouteLimits::default(),
+            panic_hook: None,
+            error_renderers: Vec::new(),
+            cors: None,
+            preflight: false,
+        }
+    }
+
     pub fn get_path(&self) -> &str {
         self.path.as_str()
     }
@@ -213,6 +439,121 @@ impl Route {
     pub fn same_thread(&mut self){
         self.threading=Threading::SAME
     } 
+
+    /// overrides the maximum body size of ```HttpCodecCfg``` for this route, larger bodies are rejected with 413
+    pub fn max_body_size(&mut self, size: usize) -> &mut Self {
+        self.limits.max_body_size = Some(size);
+        self
+    }
+
+    /// lowers the maximum number of headers for this route, more headers are rejected with 431
+    pub fn max_headers(&mut self, count: usize) -> &mut Self {
+        self.limits.max_headers = Some(count);
+        self
+    }
+
+    /// lowers the maximum length of request line and headers for this route, longer ones are rejected with 431
+    pub fn max_header_len(&mut self, len: usize) -> &mut Self {
+        self.limits.max_header_len = Some(len);
+        self
+    }
+
+    /// only accepts request bodies with one of the given media types like ```application/json```,
+    /// others are rejected with 415. Requests without a body are always accepted
+    pub fn content_types<I, S>(&mut self, media_types: I) -> &mut Self
+        where I: IntoIterator<Item=S>, S: AsRef<str> {
+        self.limits.content_types = media_types.into_iter().map(|t| t.as_ref().to_lowercase()).collect();
+        self
+    }
+
+    /// replaces all request limits of this route
+    pub fn limits(&mut self, limits: RouteLimits) -> &mut Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn get_limits(&self) -> &RouteLimits {
+        &self.limits
+    }
+
+    /// adds a middleware which is only executed for this route
+    pub fn middleware<M: Middleware>(&mut self, middleware: M) -> &mut Self {
+        self.middlewares.push(Arc::new(Box::new(middleware)));
+        self
+    }
+
+    /// runs the ```before``` middlewares, the callback and the ```after``` middlewares in reverse order.
+    /// Errors are rendered to a response the middlewares can still modify, the request id is echoed in ```X-Request-Id```.
+    /// A panicking handler is answered like an error, a panic in a middleware or renderer with a plain 500
+    pub fn process(&self, req: &mut Request) -> Response {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| self.process_unguarded(req)));
+        match result {
+            Ok(response) => response,
+            Err(payload) => {
+                self.report_panic(req, &payload);
+                let mut response = Response::from(HttpError::internal_server_error("Internal server error"));
+                if let Ok(id) = ::http::header::HeaderValue::from_str(req.id()) {
+                    response.headers_mut().insert(::request::id::REQUEST_ID_HEADER, id);
+                }
+                response
+            }
+        }
+    }
+
+    fn process_unguarded(&self, req: &mut Request) -> Response {
+        let mut executed = 0;
+        let mut result = Ok(());
+        for middleware in self.middlewares.iter() {
+            executed += 1;
+            result = middleware.before(req);
+            if result.is_err() {
+                break;
+            }
+        }
+
+        let result = match result {
+            Ok(()) => self.call_handler(req),
+            Err(err) => Err(err),
+        };
+        let mut response = match result {
+            Ok(response) => response,
+            Err(err) => {
+                warn!("Failed to handle {} {} [{}]: {:?}", self.method, self.path, req.id(), &err);
+                render_error(&self.error_renderers, req, err)
+            }
+        };
+        if let Ok(id) = ::http::header::HeaderValue::from_str(req.id()) {
+            response.headers_mut().insert(::request::id::REQUEST_ID_HEADER, id);
+        }
+
+        for middleware in self.middlewares[..executed].iter().rev() {
+            middleware.after(req, &mut response);
+        }
+        response
+    }
+
+    /// calls the handler, a panic is converted into a 500 error so neither the connection nor the event loop is affected
+    fn call_handler(&self, req: &mut Request) -> Result<Response, HttpError> {
+        let callback = &self.callback;
+        match panic::catch_unwind(AssertUnwindSafe(|| callback.handle(req))) {
+            Ok(result) => result,
+            Err(payload) => {
+                self.report_panic(req, &payload);
+                Err(HttpError::internal_server_error("Internal server error"))
+            }
+        }
+    }
+
+    /// logs the panic and calls the panic hook, a panicking hook is only logged
+    fn report_panic(&self, req: &Request, payload: &Box<Any + Send>) {
+        let info = HandlerPanic { method: self.method.clone(), path: self.path.clone(), request_id: req.id().to_string(), message: panic_message(payload) };
+        error!("Route {} {} panicked [{}]: {}", info.method, info.path, info.request_id, info.message);
+        if let Some(ref hook) = self.panic_hook {
+            if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| hook(&info))) {
+                error!("Panic hook panicked [{}]: {}", info.request_id, panic_message(&payload));
+            }
+        }
+    }
 }
 
 
@@ -317,4 +658,201 @@ mod tests {
         let router = InternalRouter::new(router);
         has_param(router.resolve(&Method::GET, "/hello/val1").unwrap().1, "hello", "val1");
     }
-}
\ No newline at end of file
+
+    struct Reject;
+
+    impl ::middleware::Middleware for Reject {
+        fn before(&self, _: &mut Request) -> Result<(), HttpError> {
+            Err(HttpError::unauthorized("nope"))
+        }
+    }
+
+    struct Tag(&'static str);
+
+    impl ::middleware::Middleware for Tag {
+        fn after(&self, _: &Request, resp: &mut Response) {
+            resp.headers_mut().append("x-tag", ::http::header::HeaderValue::from_static(self.0));
+        }
+    }
+
+    #[test]
+    fn scope_prefix_and_middlewares() {
+        let mut router = Router::new();
+        router.middleware(Tag("router"));
+        router.get("/open", handle);
+        router.scope("/admin/", |admin| {
+            admin.middleware(Tag("scope"));
+            admin.middleware(Reject);
+            admin.get("/users", handle);
+        });
+        let router = InternalRouter::new(router);
+
+        assert!(router.resolve(&Method::GET, "/users").is_none());
+        let (route, _) = router.resolve(&Method::GET, "/admin/users").unwrap();
+        let mut req = Request::get("/admin/users").unwrap();
+        let response = route.process(&mut req);
+        assert_eq!(401, response.status().as_u16());
+        let tags: Vec<&str> = response.headers().get_all("x-tag").iter().map(|v| v.to_str().unwrap()).collect();
+        assert_eq!(vec!["scope", "router"], tags);
+        assert_eq!(req.id(), response.headers().get("x-request-id").unwrap());
+
+        let (route, _) = router.resolve(&Method::GET, "/open").unwrap();
+        let mut req = Request::get("/open").unwrap();
+        let response = route.process(&mut req);
+        assert_eq!(200, response.status().as_u16());
+    }
+
+    #[test]
+    fn handler_panic() {
+        let panics = Arc::new(Mutex::new(Vec::new()));
+        let reported = panics.clone();
+        let mut router = Router::new();
+        router.middleware(Tag("router"));
+        router.panic_hook(move |info| reported.lock().unwrap().push(info.clone()));
+        router.get("/panic", |_: &mut Request| -> Result<Response, HttpError> { panic!("boom") });
+        let router = InternalRouter::new(router);
+
+        let mut req = Request::get("/panic").unwrap();
+        let response = router.resolve(&Method::GET, "/panic").unwrap().0.process(&mut req);
+        assert_eq!(500, response.status().as_u16());
+        assert_eq!("router", response.headers().get("x-tag").unwrap());
+
+        let panics = panics.lock().unwrap();
+        assert_eq!(1, panics.len());
+        assert_eq!("/panic", panics[0].path);
+        assert_eq!("boom", panics[0].message);
+        assert_eq!(req.id(), panics[0].request_id);
+    }
+
+    struct Explode;
+
+    impl ::middleware::Middleware for Explode {
+        fn before(&self, _: &mut Request) -> Result<(), HttpError> {
+            panic!("middleware")
+        }
+    }
+
+    #[test]
+    fn middleware_and_hook_panic() {
+        let mut router = Router::new();
+        router.panic_hook(|_: &HandlerPanic| panic!("hook"));
+        router.middleware(Explode);
+        router.get("/panic", handle);
+        let router = InternalRouter::new(router);
+
+        let mut req = Request::get("/panic").unwrap();
+        let response = router.resolve(&Method::GET, "/panic").unwrap().0.process(&mut req);
+        assert_eq!(500, response.status().as_u16());
+        assert_eq!(req.id(), response.headers().get("x-request-id").unwrap());
+    }
+
+    #[test]
+    fn scoped_error_renderers_and_fallback() {
+        let mut router = Router::new();
+        router.error_renderer(StatusRange::all(), |_: &Request, err: &HttpError| Response::from(format!("<p>{}</p>", err.msg)));
+        router.get("/page", |_: &mut Request| -> Result<Response, HttpError> { Err(HttpError::not_found("page")) });
+        router.scope("/api", |api| {
+            api.error_renderer(404, |_: &Request, err: &HttpError| Response::from(format!("{{\"error\":\"{}\"}}", err.msg)));
+            api.get("/users", |_: &mut Request| -> Result<Response, HttpError> { Err(HttpError::not_found("user")) });
+        });
+        router.fallback(|_: &mut Request| -> Result<Response, HttpError> { Err(HttpError::not_found("nothing")) });
+        let router = InternalRouter::new(router);
+
+        let process = |path: &str| {
+            let mut req = Request::get(path).unwrap();
+            let response = router.resolve(&Method::GET, path).unwrap().0.process(&mut req);
+            assert_eq!(404, response.status().as_u16());
+            String::from_utf8(response.into_vec().unwrap()).unwrap()
+        };
+        assert_eq!("<p>page</p>", process("/page"));
+        assert_eq!("{\"error\":\"user\"}", process("/api/users"));
+        assert_eq!("<p>nothing</p>", process("/unknown/path"));
+        assert!(router.resolve(&Method::DELETE, "/page").is_some());
+    }
+
+    #[test]
+    fn scoped_fallback_and_panic_hook() {
+        let panics = Arc::new(Mutex::new(Vec::new()));
+        let reported = panics.clone();
+        let mut router = Router::new();
+        router.panic_hook(|_: &HandlerPanic| panic!("router hook must not be called for the scope"));
+        router.scope("/api", |api| {
+            api.panic_hook(move |info| reported.lock().unwrap().push(info.path.clone()));
+            api.get("/panic", |_: &mut Request| -> Result<Response, HttpError> { panic!("boom") });
+            api.fallback(|_: &mut Request| -> Result<Response, HttpError> { Ok("api".into()) });
+            api.scope("/v2", |v2| {
+                v2.fallback(|_: &mut Request| -> Result<Response, HttpError> { Ok("v2".into()) });
+            });
+        });
+        router.fallback(|_: &mut Request| -> Result<Response, HttpError> { Ok("root".into()) });
+        let router = InternalRouter::new(router);
+
+        let body = |path: &str| {
+            let mut req = Request::get(path).unwrap();
+            let response = router.resolve(&Method::POST, path).unwrap().0.process(&mut req);
+            String::from_utf8(response.into_vec().unwrap()).unwrap()
+        };
+        assert_eq!("api", body("/api/unknown"));
+        assert_eq!("api", body("/api"));
+        assert_eq!("v2", body("/api/v2/unknown"));
+        assert_eq!("root", body("/apiary"));
+
+        let mut req = Request::get("/api/panic").unwrap();
+        let response = router.resolve(&Method::GET, "/api/panic").unwrap().0.process(&mut req);
+        assert_eq!(500, response.status().as_u16());
+        assert_eq!(vec!["/api/panic".to_string()], *panics.lock().unwrap());
+    }
+
+    #[test]
+    fn cors_preflight_routes() {
+        let mut router = Router::new();
+        router.cors(::cors::Cors::new().allow_any_origin());
+        router.middleware(Reject);
+        router.get("/hello", handle);
+        router.post("/hello", handle);
+        router.options("/custom", handle);
+        router.get("/custom", handle);
+        let router = InternalRouter::new(router);
+
+        let (route, _) = router.resolve(&Method::OPTIONS, "/hello").unwrap();
+        assert!(route.middlewares.is_empty());
+        let (route, _) = router.resolve(&Method::OPTIONS, "/custom").unwrap();
+        assert_eq!(2, route.middlewares.len());
+
+        let mut req = Request::get("/hello").unwrap();
+        let response = router.resolve(&Method::GET, "/hello").unwrap().0.process(&mut req);
+        assert_eq!(401, response.status().as_u16());
+    }
+
+    #[test]
+    fn scoped_cors_replaces_router_cors() {
+        let mut router = Router::new();
+        router.cors(::cors::Cors::new().allow_origin("https://first.example.com"));
+        router.cors(::cors::Cors::new().allow_origin("https://app.example.com"));
+        router.get("/app", handle);
+        router.scope("/public", |public| {
+            public.cors(::cors::Cors::new().allow_any_origin());
+            public.get("/feed", handle);
+        });
+        let router = InternalRouter::new(router);
+
+        let (route, _) = router.resolve(&Method::GET, "/app").unwrap();
+        assert_eq!(1, route.middlewares.len());
+        let (route, _) = router.resolve(&Method::GET, "/public/feed").unwrap();
+        assert_eq!(1, route.middlewares.len());
+
+        let origin = |path: &str, origin: &'static str| {
+            let mut req = ::http::Request::new(::body::Body(None));
+            *req.method_mut() = Method::OPTIONS;
+            *req.uri_mut() = path.parse().unwrap();
+            req.headers_mut().insert(::http::header::ORIGIN, ::http::header::HeaderValue::from_static(origin));
+            req.headers_mut().insert(::http::header::ACCESS_CONTROL_REQUEST_METHOD, ::http::header::HeaderValue::from_static("GET"));
+            let mut req = Request::from_http(req);
+            let response = router.resolve(&Method::OPTIONS, path).unwrap().0.process(&mut req);
+            response.headers().get(::http::header::ACCESS_CONTROL_ALLOW_ORIGIN).map(|v| v.to_str().unwrap().to_string())
+        };
+        assert_eq!(Some("https://app.example.com".to_string()), origin("/app", "https://app.example.com"));
+        assert_eq!(None, origin("/app", "https://first.example.com"));
+        assert!(origin("/public/feed", "https://other.example.com").is_some());
+    }
+}
@@ -0,0 +1,137 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::ops::Range;
+use std::sync::Arc;
+use http::StatusCode;
+
+use error::HttpError;
+use problem::{ProblemJson, accepts_problem_json};
+use request::Request;
+use response::Response;
+
+/// Renders the response of an error, eg. an html error page or a json document.
+/// Status and headers of the error are applied to the rendered response
+pub trait ErrorRenderer: Send + Sync + 'static {
+    fn render(&self, req: &Request, err: &HttpError) -> Response;
+}
+
+impl<F> ErrorRenderer for F
+    where F: Send + Sync + 'static + Fn(&Request, &HttpError) -> Response {
+    fn render(&self, req: &Request, err: &HttpError) -> Response {
+        (*self)(req, err)
+    }
+}
+
+/// Status codes an error renderer is registered for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatusRange {
+    from: u16,
+    /// exclusive, wide enough to hold ```u16::MAX + 1```
+    to: u32,
+}
+
+impl StatusRange {
+    /// all 4xx errors
+    pub fn client_errors() -> Self {
+        StatusRange { from: 400, to: 500 }
+    }
+
+    /// all 5xx errors
+    pub fn server_errors() -> Self {
+        StatusRange { from: 500, to: 600 }
+    }
+
+    /// every status code
+    pub fn all() -> Self {
+        StatusRange { from: 0, to: u16::max_value() as u32 + 1 }
+    }
+
+    pub fn contains(&self, status: StatusCode) -> bool {
+        let status = status.as_u16();
+        status >= self.from && (status as u32) < self.to
+    }
+}
+
+impl From<u16> for StatusRange {
+    fn from(status: u16) -> Self {
+        StatusRange { from: status, to: status as u32 + 1 }
+    }
+}
+
+impl From<StatusCode> for StatusRange {
+    fn from(status: StatusCode) -> Self {
+        status.as_u16().into()
+    }
+}
+
+impl From<Range<u16>> for StatusRange {
+    fn from(range: Range<u16>) -> Self {
+        StatusRange { from: range.start, to: range.end as u32 }
+    }
+}
+
+pub(crate) type Renderers = Vec<(StatusRange, Arc<Box<ErrorRenderer>>)>;
+
+/// converts the error with the first matching renderer, the plain text message is used if none matches.
+/// Clients explicitly accepting ```application/problem+json``` always get problem details
+pub(crate) fn render_error(renderers: &[(StatusRange, Arc<Box<ErrorRenderer>>)], req: &Request, err: HttpError) -> Response {
+    let response = if accepts_problem_json(req) {
+        ProblemJson.render(req, &err)
+    } else {
+        match renderers.iter().find(|&&(ref range, _)| range.contains(err.status)) {
+            Some(&(_, ref renderer)) => renderer.render(req, &err),
+            None => return Response::from(err),
+        }
+    };
+    err.log();
+    let mut response = response.into_inner();
+    *response.status_mut() = err.status;
+    for (name, value) in err.headers.iter() {
+        if !response.headers().contains_key(name) {
+            response.headers_mut().insert(name.clone(), value.clone());
+        }
+    }
+    Response::from_http(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_matching_renderer() {
+        let page = |_: &Request, err: &HttpError| Response::from(format!("<h1>{}</h1>", err.msg));
+        let json = |_: &Request, err: &HttpError| Response::from(format!("{{\"error\": \"{}\"}}", err.msg));
+        let mut renderers: Renderers = Vec::new();
+        renderers.push((StatusCode::NOT_FOUND.into(), Arc::new(Box::new(page))));
+        renderers.push((StatusRange::client_errors(), Arc::new(Box::new(json))));
+        let req = Request::get("/").unwrap();
+
+        let response = render_error(&renderers, &req, HttpError::not_found("missing"));
+        assert_eq!(404, response.status().as_u16());
+        assert_eq!(Some(b"<h1>missing</h1>".to_vec()), response.into_vec());
+
+        let response = render_error(&renderers, &req, HttpError::unauthorized_challenge("Basic realm=\"admin\"", "denied"));
+        assert_eq!(401, response.status().as_u16());
+        assert_eq!("Basic realm=\"admin\"", response.headers().get(::http::header::WWW_AUTHENTICATE).unwrap());
+        assert_eq!(Some(b"{\"error\": \"denied\"}".to_vec()), response.into_vec());
+
+        let response = render_error(&renderers, &req, HttpError::internal_server_error("failed"));
+        assert_eq!(Some(b"failed".to_vec()), response.into_vec());
+    }
+
+    #[test]
+    fn status_range_bounds() {
+        assert_eq!(StatusRange { from: 65535, to: 65536 }, StatusRange::from(65535));
+        assert!(StatusRange::from(599).contains(StatusCode::from_u16(599).unwrap()));
+        assert!(!StatusRange::from(400..404).contains(StatusCode::NOT_FOUND));
+        assert!(StatusRange::all().contains(StatusCode::from_u16(999).unwrap()));
+    }
+}
@@ -9,6 +9,7 @@
 
 use std::path::{PathBuf, Path};
 use std::sync::RwLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::collections::HashMap;
 use response::Response;
 use error::HttpError;
@@ -17,10 +18,25 @@ use std::fs::File;
 use std::io::Read;
 use mime_guess::{Mime, guess_mime_type_opt};
 use std::time::SystemTime;
+use http::StatusCode;
+use http::header::{ACCEPT_RANGES, CONTENT_RANGE, CONTENT_TYPE, ETAG};
+use super::range::{ByteRanges, RangeRequest};
 
 pub struct StaticFileCache {
     entry_map: RwLock<HashMap<PathBuf, CacheEntry>>,
     max_size: usize,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+/// Usage statistics of the static file cache
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    /// cached bytes
+    pub size: usize,
+    pub entries: usize,
 }
 
 #[derive(Debug)]
@@ -68,7 +84,7 @@ impl Default for EvictionPolicy {
 
 impl Default for StaticFileCache {
     fn default() -> Self {
-        StaticFileCache { entry_map: RwLock::new(HashMap::new()), max_size: 50_000_000 }
+        StaticFileCache::with_max_size(50_000_000)
     }
 }
 
@@ -78,10 +94,25 @@ impl StaticFileCache {
     }
 
     pub fn with_max_size(size: usize) -> Self {
-        StaticFileCache { entry_map: RwLock::new(HashMap::new()), max_size: size }
+        StaticFileCache { entry_map: RwLock::new(HashMap::new()), max_size: size, hits: AtomicUsize::new(0), misses: AtomicUsize::new(0) }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let map = self.entry_map.read().unwrap();
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            size: map.values().map(|e| e.data.len()).sum(),
+            entries: map.len(),
+        }
     }
 
     pub fn get_or_load(&self, path: &PathBuf, change_detection: ChangeDetection, evction_policy: EvictionPolicy, etag: Option<&str>) -> Result<Response, HttpError> {
+        self.get_or_load_range(path, change_detection, evction_policy, etag, RangeRequest::default())
+    }
+
+    /// like ```get_or_load```, answers ```Range``` requests with 206 partial content or 416 if no range is satisfiable
+    pub fn get_or_load_range(&self, path: &PathBuf, change_detection: ChangeDetection, evction_policy: EvictionPolicy, etag: Option<&str>, range: RangeRequest) -> Result<Response, HttpError> {
         use std::ops::DerefMut;
 
         let mut lock = self.entry_map.write().unwrap();
@@ -101,18 +132,15 @@ impl StaticFileCache {
                     if duration > timeout {
                         None
                     } else {
-                        let v = entry.data.clone();
-                        Some(create_response(v, &entry.mime_type, &entry.checksum, etag))
+                        Some(create_response(&entry.data, &entry.mime_type, &entry.checksum, etag, range))
                     }
                 }
                 ChangeDetection::Never => {
-                    let v = entry.data.clone();
-                    Some(create_response(v, &entry.mime_type, &entry.checksum, etag))
+                    Some(create_response(&entry.data, &entry.mime_type, &entry.checksum, etag, range))
                 }
                 ChangeDetection::FileInfoChange => {
                     if !file_changed(&entry.path, &entry.last_modification)? {
-                        let v = entry.data.clone();
-                        Some(create_response(v, &entry.mime_type, &entry.checksum, etag))
+                        Some(create_response(&entry.data, &entry.mime_type, &entry.checksum, etag, range))
                     } else {
                         None
                     }
@@ -122,14 +150,18 @@ impl StaticFileCache {
         } else { None };
 
         match found {
-            Some(r) => r,
+            Some(r) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                r
+            }
             None => {
-                self.load_file(map, path, change_detection, evction_policy, etag)
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                self.load_file(map, path, change_detection, evction_policy, etag, range)
             }
         }
     }
 
-    fn load_file(&self, map: &mut HashMap<PathBuf, CacheEntry>, path: &PathBuf, change_detection: ChangeDetection, evction_policy: EvictionPolicy, etag: Option<&str>) -> Result<Response, HttpError> {
+    fn load_file(&self, map: &mut HashMap<PathBuf, CacheEntry>, path: &PathBuf, change_detection: ChangeDetection, evction_policy: EvictionPolicy, etag: Option<&str>, range: RangeRequest) -> Result<Response, HttpError> {
         let data = load_file(path, self.max_size)?;
         let data_size = data.len();
         let checksum = checksum(data.as_ref());
@@ -139,10 +171,10 @@ impl StaticFileCache {
 
         let mime_type_option = guess_mime_type_opt(path);
 
+        let response = create_response(&data, &mime_type_option, &checksum.bytes(), etag, range);
         if change_detection == ChangeDetection::NoCache {
-            return create_response(data, &mime_type_option, &checksum.bytes(), etag);
+            return response;
         }
-        let retval = data.clone();
 
         let modification = ::std::fs::metadata(path)?.modified()?;
 
@@ -162,7 +194,7 @@ impl StaticFileCache {
             map.insert(path.clone(), entry);
         }
 
-        create_response(retval, &mime_type_option, &checksum.bytes(), etag)
+        response
     }
 }
 
@@ -171,7 +203,7 @@ fn file_changed(path: &Path, time: &SystemTime) -> ::std::io::Result<bool> {
     Ok(modification != *time)
 }
 
-fn create_response(data: Vec<u8>, mime: &Option<Mime>, checksum: &[u8], etag: Option<&str>) -> Result<Response, HttpError> {
+fn create_response(data: &[u8], mime: &Option<Mime>, checksum: &[u8], etag: Option<&str>, range: RangeRequest) -> Result<Response, HttpError> {
     let mut checksum_string = String::with_capacity(20);
     for byte in checksum.iter() {
         checksum_string.push_str(format!("{:02X}", byte).as_str());
@@ -183,15 +215,59 @@ fn create_response(data: Vec<u8>, mime: &Option<Mime>, checksum: &[u8], etag: Op
         }
     }
 
-    let mut response = Response::from(data); //fixme would be better if response is not owning vec but could just use this vec
-    if let &Some(ref mime) = mime {
-        response.set_header(::http::header::CONTENT_TYPE, format!("{}", mime))?;
-    }
+    let mime = mime.as_ref().map(|mime| mime.to_string());
+    let mut response = match range.resolve(&checksum_string, data.len()) {
+        ByteRanges::Full => {
+            let mut response = Response::from(data.to_vec()); //fixme would be better if response is not owning vec but could just use this vec
+            if let Some(ref mime) = mime {
+                response.set_header(CONTENT_TYPE, mime)?;
+            }
+            response
+        }
+        ByteRanges::Partial(ref ranges) if ranges.len() == 1 => {
+            let range = &ranges[0];
+            let mut response = Response::builder().status(StatusCode::PARTIAL_CONTENT)
+                .header_str_value(CONTENT_RANGE, content_range(range, data.len()))?
+                .body_vec(&data[range.clone()])
+                .build()?;
+            if let Some(ref mime) = mime {
+                response.set_header(CONTENT_TYPE, mime)?;
+            }
+            response
+        }
+        ByteRanges::Partial(ranges) => {
+            let boundary = format!("byteranges_{}", checksum_string);
+            let mut body = Vec::new();
+            for range in ranges.iter() {
+                body.extend_from_slice(format!("\r\n--{}\r\n", boundary).as_bytes());
+                if let Some(ref mime) = mime {
+                    body.extend_from_slice(format!("Content-Type: {}\r\n", mime).as_bytes());
+                }
+                body.extend_from_slice(format!("Content-Range: {}\r\n\r\n", content_range(range, data.len())).as_bytes());
+                body.extend_from_slice(&data[range.clone()]);
+            }
+            body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+            Response::builder().status(StatusCode::PARTIAL_CONTENT)
+                .header_str_value(CONTENT_TYPE, format!("multipart/byteranges; boundary={}", boundary))?
+                .body_vec(body)
+                .build()?
+        }
+        ByteRanges::Unsatisfiable => {
+            let mut err = HttpError::range_not_satisfiable(format!("Range not satisfiable for {} bytes", data.len()));
+            err.headers.insert(CONTENT_RANGE, format!("bytes */{}", data.len()).parse()?);
+            return Err(err);
+        }
+    };
+    response.set_header(ACCEPT_RANGES, "bytes")?;
     //fixme this is ugly
-    response.set_header(::http::header::ETAG, checksum_string)?;
+    response.set_header(ETAG, checksum_string)?;
     Ok(response)
 }
 
+fn content_range(range: &::std::ops::Range<usize>, len: usize) -> String {
+    format!("bytes {}-{}/{}", range.start, range.end - 1, len)
+}
+
 fn check_eviction_size(map: &mut HashMap<PathBuf, CacheEntry>, new_element_size: usize, max_cache_size: usize) -> bool {
     let cache_size = |map: &HashMap<PathBuf, CacheEntry>| {
         let sum: usize = map.values().map(|v| v.data.len()).sum();
@@ -279,6 +355,11 @@ mod tests {
         let needle = "<h1 class=\"MyStyle\">Hello world</h1>";
         let haystack = String::from_utf8(body).unwrap();
         assert!(haystack.contains(needle));
+
+        cache.get_or_load(&buf, ChangeDetection::Never, EvictionPolicy::Never, None).unwrap();
+        let stats = cache.stats();
+        assert_eq!((1, 1, 1), (stats.hits, stats.misses, stats.entries));
+        assert_eq!(haystack.len(), stats.size);
     }
 
     #[test]
@@ -397,6 +478,42 @@ mod tests {
         assert_eq!(::http::StatusCode::NOT_MODIFIED, response.status());
     }
 
+    #[test]
+    fn ranges() {
+        let cache = StaticFileCache::new();
+        let dir = TempDir::new("cachetest").unwrap();
+        let path = dir.path().join("test.txt");
+        write_to_file(&path, "0123456789").unwrap();
+
+        for &change_detection in [ChangeDetection::Never, ChangeDetection::NoCache].iter() {
+            let get = |range: &str| cache.get_or_load_range(&path, change_detection, EvictionPolicy::Never, None, RangeRequest { range: Some(range), if_range: None });
+
+            let response = cache.get_or_load(&path, change_detection, EvictionPolicy::Never, None).unwrap();
+            assert_eq!(StatusCode::OK, response.status());
+            assert_eq!("bytes", response.headers().get(ACCEPT_RANGES).unwrap());
+
+            let response = get("bytes=2-4").unwrap();
+            assert_eq!(StatusCode::PARTIAL_CONTENT, response.status());
+            assert_eq!("bytes 2-4/10", response.headers().get(CONTENT_RANGE).unwrap());
+            assert_eq!(b"234".to_vec(), response.into_vec().unwrap());
+
+            let response = get("bytes=0-1,-2").unwrap();
+            assert_eq!(StatusCode::PARTIAL_CONTENT, response.status());
+            let content_type = response.headers().get(CONTENT_TYPE).unwrap().to_str().unwrap().to_string();
+            assert!(content_type.starts_with("multipart/byteranges; boundary="));
+            let body = String::from_utf8(response.into_vec().unwrap()).unwrap();
+            assert!(body.contains("Content-Range: bytes 0-1/10\r\n\r\n01\r\n"));
+            assert!(body.contains("Content-Range: bytes 8-9/10\r\n\r\n89\r\n"));
+
+            let err = get("bytes=20-").unwrap_err();
+            assert_eq!(416, err.status.as_u16());
+            assert_eq!("bytes */10", err.headers.get(CONTENT_RANGE).unwrap());
+        }
+
+        let response = cache.get_or_load_range(&path, ChangeDetection::Never, EvictionPolicy::Never, None, RangeRequest { range: Some("bytes=0-0"), if_range: Some("\"outdated\"") }).unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+    }
+
     fn write_to_file(path: &PathBuf, content: &str) -> ::std::io::Result<()> {
         use std::io::Write;
 
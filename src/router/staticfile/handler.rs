@@ -15,6 +15,7 @@ use response::Response;
 use error::HttpError;
 use request::Request;
 use super::cache::{StaticFileCache, EvictionPolicy, ChangeDetection};
+use super::range::RangeRequest;
 use std::sync::Arc;
 
 pub struct StaticFileHandler {
@@ -34,6 +35,7 @@ impl StaticFileHandler {
 impl Handler for StaticFileHandler {
     fn handle(&self, req: &mut Request) -> Result<Response, HttpError> {
         let o = req.header(&::http::header::ETAG);
+        let range = RangeRequest { range: req.header(&::http::header::RANGE), if_range: req.header(&::http::header::IF_RANGE) };
 
         if self.path.is_dir() {
             let mut file_in_dir = self.path.clone();
@@ -42,16 +44,15 @@ impl Handler for StaticFileHandler {
 
             file_in_dir.push(file_name);
 
-            println!("file dir={:?}, path={:?}", file_in_dir, self.path);
-            println!("file dir={:?}, path={:?}", file_in_dir.canonicalize()?, self.path.canonicalize()?);
+            trace!("file dir={:?}, path={:?}", file_in_dir, self.path);
             if !file_in_dir.canonicalize()?.starts_with(&self.path.canonicalize()?) {
                 return Err(HttpError::not_found(file_name));
             }
             
 
-            self.cache.get_or_load(&file_in_dir, self.change_detection, self.eviction_policy, o)
+            self.cache.get_or_load_range(&file_in_dir, self.change_detection, self.eviction_policy, o, range)
         } else {
-            self.cache.get_or_load(&self.path, self.change_detection, self.eviction_policy, o)
+            self.cache.get_or_load_range(&self.path, self.change_detection, self.eviction_policy, o, range)
         }
     }
 }
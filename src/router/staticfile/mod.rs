@@ -9,6 +9,8 @@
 
 pub mod cache;
 pub mod handler;
+pub mod range;
 
 pub use self::cache::{StaticFileCache, EvictionPolicy, ChangeDetection};
+pub use self::range::RangeRequest;
 pub use self::handler::StaticFileHandler;
\ No newline at end of file
@@ -0,0 +1,143 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::ops::Range;
+
+/// more ranges are ignored and answered with the whole file
+const MAX_RANGES: usize = 32;
+
+/// ```Range``` and ```If-Range``` headers of a request
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RangeRequest<'a> {
+    pub range: Option<&'a str>,
+    pub if_range: Option<&'a str>,
+}
+
+/// Byte ranges of a ```Range``` header resolved against the length of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ByteRanges {
+    /// no or an ignored range header, the whole file is sent
+    Full,
+    /// sorted, non overlapping ranges
+    Partial(Vec<Range<usize>>),
+    /// none of the ranges overlaps the file
+    Unsatisfiable,
+}
+
+impl<'a> RangeRequest<'a> {
+    /// resolves the ranges, the ```Range``` header is ignored if ```If-Range``` doesn't match the etag of the file
+    pub(crate) fn resolve(&self, etag: &str, len: usize) -> ByteRanges {
+        let range = match self.range {
+            Some(range) => range,
+            None => return ByteRanges::Full,
+        };
+        if let Some(if_range) = self.if_range {
+            if if_range.trim().trim_matches('"') != etag {
+                return ByteRanges::Full;
+            }
+        }
+        parse(range, len)
+    }
+}
+
+/// parses ```bytes=0-99,200-,-50```, malformed headers are ignored as defined in RFC 7233
+fn parse(header: &str, len: usize) -> ByteRanges {
+    let header = header.trim();
+    if !header.starts_with("bytes=") {
+        return ByteRanges::Full;
+    }
+    let specs: Vec<&str> = header["bytes=".len()..].split(',').map(str::trim).filter(|s| !s.is_empty()).collect();
+    if specs.is_empty() || specs.len() > MAX_RANGES {
+        return ByteRanges::Full;
+    }
+
+    let mut ranges = Vec::with_capacity(specs.len());
+    for spec in specs {
+        let mut bounds = spec.splitn(2, '-');
+        let (first, last) = match (bounds.next(), bounds.next()) {
+            (Some(first), Some(last)) => (first.trim(), last.trim()),
+            _ => return ByteRanges::Full,
+        };
+        let range = if first.is_empty() {
+            match last.parse::<usize>() {
+                Ok(0) => None,
+                Ok(suffix) => Some(len.saturating_sub(suffix)..len),
+                Err(_) => return ByteRanges::Full,
+            }
+        } else {
+            let first = match first.parse::<usize>() {
+                Ok(first) => first,
+                Err(_) => return ByteRanges::Full,
+            };
+            let last = if last.is_empty() {
+                len
+            } else {
+                match last.parse::<usize>() {
+                    Ok(last) if last >= first => last.saturating_add(1).min(len),
+                    _ => return ByteRanges::Full,
+                }
+            };
+            Some(first..last)
+        };
+        if let Some(range) = range {
+            if range.start < len {
+                ranges.push(range);
+            }
+        }
+    }
+    if ranges.is_empty() {
+        return ByteRanges::Unsatisfiable;
+    }
+
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        let overlapping = merged.last().map(|last| range.start <= last.end).unwrap_or(false);
+        if overlapping {
+            let last = merged.last_mut().unwrap();
+            last.end = last.end.max(range.end);
+        } else {
+            merged.push(range);
+        }
+    }
+    ByteRanges::Partial(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(range: &str) -> RangeRequest {
+        RangeRequest { range: Some(range), if_range: None }
+    }
+
+    #[test]
+    fn parse_ranges() {
+        assert_eq!(ByteRanges::Partial(vec![0..100]), request("bytes=0-99").resolve("", 1000));
+        assert_eq!(ByteRanges::Partial(vec![900..1000]), request("bytes=-100").resolve("", 1000));
+        assert_eq!(ByteRanges::Partial(vec![500..1000]), request("bytes=500-").resolve("", 1000));
+        assert_eq!(ByteRanges::Partial(vec![990..1000]), request("bytes=990-2000").resolve("", 1000));
+        assert_eq!(ByteRanges::Partial(vec![0..1000]), request("bytes=-2000").resolve("", 1000));
+        assert_eq!(ByteRanges::Partial(vec![0..20, 50..60]), request("bytes=50-59, 0-9, 5-19").resolve("", 1000));
+        assert_eq!(ByteRanges::Partial(vec![0..10]), request("bytes=0-9,1000-").resolve("", 1000));
+        assert_eq!(ByteRanges::Unsatisfiable, request("bytes=1000-").resolve("", 1000));
+        assert_eq!(ByteRanges::Unsatisfiable, request("bytes=-0").resolve("", 1000));
+        assert_eq!(ByteRanges::Full, request("bytes=9-0").resolve("", 1000));
+        assert_eq!(ByteRanges::Full, request("lines=0-9").resolve("", 1000));
+        assert_eq!(ByteRanges::Full, request("bytes=a-b").resolve("", 1000));
+        assert_eq!(ByteRanges::Full, RangeRequest::default().resolve("", 1000));
+    }
+
+    #[test]
+    fn if_range() {
+        let matching = RangeRequest { range: Some("bytes=0-9"), if_range: Some("\"ABCD\"") };
+        assert_eq!(ByteRanges::Partial(vec![0..10]), matching.resolve("ABCD", 1000));
+        assert_eq!(ByteRanges::Full, matching.resolve("EF01", 1000));
+    }
+}
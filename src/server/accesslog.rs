@@ -0,0 +1,283 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Access logging, one line per request in Common, Combined or JSON format.
+//! Lines are either written to the ```log``` crate with target ```access``` or to a file.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use request::Request;
+use response::Response;
+use request::id::REQUEST_ID_HEADER;
+use super::codec::RejectedRequest;
+
+/// Format of the access log lines
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AccessLogFormat {
+    /// NCSA common log format
+    Common,
+    /// NCSA combined log format, followed by the request duration in microseconds (like apache's ```%D```)
+    /// and the request id
+    Combined,
+    /// one json object per line
+    Json,
+}
+
+enum Target {
+    Log,
+    File(FileTarget),
+}
+
+struct FileTarget {
+    path: PathBuf,
+    file: Mutex<File>,
+    reopen: Arc<AtomicBool>,
+}
+
+impl FileTarget {
+    fn write(&self, line: &str) -> io::Result<()> {
+        let mut file = self.file.lock().unwrap();
+        if self.reopen.swap(false, Ordering::SeqCst) {
+            debug!("Reopening access log {:?}", self.path);
+            *file = open(&self.path)?;
+        }
+        file.write_all(line.as_bytes())?;
+        file.write_all(b"\n")
+    }
+}
+
+fn open(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Access log configuration, see ```Server::set_access_log```
+pub struct AccessLog {
+    format: AccessLogFormat,
+    target: Target,
+}
+
+impl AccessLog {
+    /// logs via the ```log``` crate at info level with target ```access```
+    pub fn log(format: AccessLogFormat) -> Self {
+        AccessLog { format, target: Target::Log }
+    }
+
+    /// appends to the given file.
+    /// On unix the file is reopened after a SIGUSR1, so it can be rotated by logrotate.
+    pub fn file<P: Into<PathBuf>>(format: AccessLogFormat, path: P) -> io::Result<Self> {
+        let path = path.into();
+        let file = open(&path)?;
+        let reopen = Arc::new(AtomicBool::new(false));
+        register_reopen_signal(&reopen);
+        Ok(AccessLog { format, target: Target::File(FileTarget { path, file: Mutex::new(file), reopen }) })
+    }
+
+    /// reopens the log file before the next line is written, does nothing for the log target
+    pub fn reopen(&self) {
+        if let Target::File(ref target) = self.target {
+            target.reopen.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// writes a line for the given entry
+    pub fn write(&self, entry: &AccessLogEntry) {
+        let line = entry.format(self.format);
+        match self.target {
+            Target::Log => info!(target: "access", "{}", line),
+            Target::File(ref target) => {
+                if let Err(e) = target.write(&line) {
+                    error!("Could not write access log {:?}: {}", target.path, e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn register_reopen_signal(flag: &Arc<AtomicBool>) {
+    if let Err(e) = ::signal_hook::flag::register(::signal_hook::SIGUSR1, flag.clone()) {
+        warn!("Could not register SIGUSR1 for access log reopening: {}", e);
+    }
+}
+
+#[cfg(not(unix))]
+fn register_reopen_signal(_: &Arc<AtomicBool>) {}
+
+/// Data of a single access log line
+#[derive(Debug, Clone, Serialize)]
+pub struct AccessLogEntry {
+    #[serde(serialize_with = "serialize_time")]
+    pub time: SystemTime,
+    pub remote_addr: Option<IpAddr>,
+    pub user: Option<String>,
+    pub method: String,
+    pub path: String,
+    pub version: String,
+    pub status: u16,
+    pub bytes: usize,
+    #[serde(serialize_with = "serialize_duration")]
+    pub duration: Duration,
+    pub referer: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: Option<String>,
+}
+
+impl AccessLogEntry {
+    /// creates an entry for a handled request
+    pub fn new(req: &Request, resp: &Response, duration: Duration) -> Self {
+        AccessLogEntry {
+            time: SystemTime::now(),
+            remote_addr: req.client_ip(),
+            user: req.principal().map(|p| p.name.clone()),
+            method: req.method().to_string(),
+            path: req.uri().to_string(),
+            version: format!("{:?}", req.version()),
+            status: resp.status().as_u16(),
+            bytes: resp.body().inner().as_ref().map(|b| b.len()).unwrap_or(0),
+            duration,
+            referer: req.header(&::http::header::REFERER).map(|s| s.to_string()),
+            user_agent: req.header(&::http::header::USER_AGENT).map(|s| s.to_string()),
+            request_id: Some(req.id().to_string()),
+        }
+    }
+
+    /// creates an entry for a request that was rejected while decoding
+    pub fn rejected(rejected: &RejectedRequest, resp: &Response, duration: Duration) -> Self {
+        let request_id = resp.headers().get(REQUEST_ID_HEADER).and_then(|v| v.to_str().ok()).map(|s| s.to_string());
+        let header = |name| rejected.headers.get(name).and_then(|v: &::http::header::HeaderValue| v.to_str().ok()).map(|s: &str| s.to_string());
+        AccessLogEntry {
+            time: SystemTime::now(),
+            remote_addr: rejected.remote_addr.map(|a| a.ip()),
+            user: None,
+            method: rejected.method.as_ref().map(|m| m.to_string()).unwrap_or_else(|| "-".into()),
+            path: rejected.uri.as_ref().map(|u| u.to_string()).unwrap_or_else(|| "-".into()),
+            version: format!("{:?}", rejected.version),
+            status: resp.status().as_u16(),
+            bytes: resp.body().inner().as_ref().map(|b| b.len()).unwrap_or(0),
+            duration,
+            referer: header(::http::header::REFERER),
+            user_agent: header(::http::header::USER_AGENT),
+            request_id,
+        }
+    }
+
+    fn format(&self, format: AccessLogFormat) -> String {
+        match format {
+            AccessLogFormat::Common => self.common(),
+            AccessLogFormat::Combined => {
+                format!("{} \"{}\" \"{}\" {} {}", self.common(), escape(self.referer.as_ref()), escape(self.user_agent.as_ref()), micros(&self.duration),
+                        self.request_id.as_ref().map(|s| s.as_str()).unwrap_or("-"))
+            }
+            AccessLogFormat::Json => ::serde_json::to_string(self).unwrap_or_else(|e| format!("{{\"error\":\"{}\"}}", e)),
+        }
+    }
+
+    fn common(&self) -> String {
+        let remote = self.remote_addr.map(|ip| ip.to_string()).unwrap_or_else(|| "-".into());
+        let bytes = if self.bytes == 0 { "-".into() } else { self.bytes.to_string() };
+        format!("{} - {} [{}] \"{} {} {}\" {} {}", remote, escape(self.user.as_ref()), clf_time(&self.time),
+                self.method, self.path, self.version, self.status, bytes)
+    }
+}
+
+fn escape(value: Option<&String>) -> String {
+    match value {
+        Some(value) => value.replace('\\', "\\\\").replace('"', "\\\""),
+        None => "-".into(),
+    }
+}
+
+fn micros(duration: &Duration) -> u64 {
+    duration.as_secs() * 1_000_000 + (duration.subsec_nanos() / 1_000) as u64
+}
+
+fn serialize_duration<S: ::serde::Serializer>(duration: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_u64(micros(duration))
+}
+
+fn serialize_time<S: ::serde::Serializer>(time: &SystemTime, s: S) -> Result<S::Ok, S::Error> {
+    let (year, month, day, hour, minute, second) = civil(time);
+    s.serialize_str(&format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, hour, minute, second))
+}
+
+fn clf_time(time: &SystemTime) -> String {
+    const MONTHS: [&'static str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let (year, month, day, hour, minute, second) = civil(time);
+    format!("{:02}/{}/{:04}:{:02}:{:02}:{:02} +0000", day, MONTHS[(month - 1) as usize], year, hour, minute, second)
+}
+
+/// converts to utc year, month, day, hour, minute, second
+fn civil(time: &SystemTime) -> (i64, u32, u32, u32, u32, u32) {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+    let days = secs / 86_400;
+    let rem = secs % 86_400;
+
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = (if z >= 0 { z } else { z - 146_096 }) / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + (if month <= 2 { 1 } else { 0 });
+
+    (year, month, day, (rem / 3600) as u32, (rem % 3600 / 60) as u32, (rem % 60) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> AccessLogEntry {
+        AccessLogEntry {
+            time: UNIX_EPOCH + Duration::from_secs(971_186_136),
+            remote_addr: Some("127.0.0.1".parse().unwrap()),
+            user: Some("frank".into()),
+            method: "GET".into(),
+            path: "/apache_pb.gif".into(),
+            version: "HTTP/1.0".into(),
+            status: 200,
+            bytes: 2326,
+            duration: Duration::from_millis(12),
+            referer: Some("http://www.example.com/start.html".into()),
+            user_agent: None,
+            request_id: Some("abc-123".into()),
+        }
+    }
+
+    #[test]
+    fn common() {
+        assert_eq!("127.0.0.1 - frank [10/Oct/2000:13:55:36 +0000] \"GET /apache_pb.gif HTTP/1.0\" 200 2326",
+                   entry().format(AccessLogFormat::Common));
+    }
+
+    #[test]
+    fn combined() {
+        assert_eq!("127.0.0.1 - frank [10/Oct/2000:13:55:36 +0000] \"GET /apache_pb.gif HTTP/1.0\" 200 2326 \"http://www.example.com/start.html\" \"-\" 12000 abc-123",
+                   entry().format(AccessLogFormat::Combined));
+    }
+
+    #[test]
+    fn json() {
+        let line = entry().format(AccessLogFormat::Json);
+        assert!(line.contains("\"time\":\"2000-10-10T13:55:36Z\""), line);
+        assert!(line.contains("\"duration\":12000"), line);
+        assert!(line.contains("\"status\":200"), line);
+        assert!(line.contains("\"request_id\":\"abc-123\""), line);
+    }
+}
@@ -15,30 +15,68 @@ use http::{Request, Response, Method, Uri, Version};
 use http::header::{HeaderValue, HeaderName, HeaderMap};
 use http::request::Builder as RequestBuilder;
 use std::str::FromStr;
-use ::router::{InternalRouter, Route};
+use ::router::{InternalRouter, LimitViolation, Route};
 use std::sync::Arc;
+use std::net::SocketAddr;
+use tokio_core::net::TcpStream;
 
 use ::body::Body;
 use ::request::Params;
+use ::metrics::ConnectionGuard;
+use super::unix::PeerCredentials;
 
 pub struct Http {
     pub router: Arc<InternalRouter>,
     pub config: HttpCodecCfg,
 }
 
-impl<T: AsyncRead + AsyncWrite + 'static> ServerProto<T> for Http {
+/// Transports that know the address of their peer
+pub trait PeerAddr {
+    fn peer_addr(&self) -> Option<SocketAddr>;
+
+    /// credentials of the peer process, only known for unix domain sockets
+    fn peer_credentials(&self) -> Option<PeerCredentials> {
+        None
+    }
+
+    /// subject of the verified client certificate, only known for tls connections
+    fn peer_certificate(&self) -> Option<String> {
+        None
+    }
+}
+
+impl PeerAddr for TcpStream {
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        TcpStream::peer_addr(self).ok()
+    }
+}
+
+#[cfg(unix)]
+impl PeerAddr for ::tokio_uds::UnixStream {
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    fn peer_credentials(&self) -> Option<PeerCredentials> {
+        super::unix::peer_credentials(self)
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite + PeerAddr + 'static> ServerProto<T> for Http {
     type Request = DecodingResult;
     type Response = Response<Body>;
     type Transport = Framed<T, HttpCodec>;
     type BindTransport = io::Result<Framed<T, HttpCodec>>;
 
     fn bind_transport(&self, io: T) -> io::Result<Framed<T, HttpCodec>> {
-        let codec = HttpCodec { config: self.config.clone(), router: self.router.clone(), request: None };
+        let connection = self.router.metrics().map(|m| ConnectionGuard::new(m.clone()));
+        let codec = HttpCodec { config: self.config.clone(), router: self.router.clone(), request: None, remote_addr: io.peer_addr(), peer_credentials: io.peer_credentials(), peer_certificate: io.peer_certificate(), connection };
         Ok(io.framed(codec))
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+/// Limits of the http parser
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct HttpCodecCfg {
     max_reuest_header_len: usize,
     max_body_size: usize,
@@ -51,10 +89,40 @@ impl Default for HttpCodecCfg {
     }
 }
 
+impl HttpCodecCfg {
+    /// the default limits: 8000 bytes of request line and headers, 64 headers and 20_000_000 bytes of body
+    pub fn new() -> Self {
+        HttpCodecCfg::default()
+    }
+
+    /// maximum length of the request line and headers in bytes
+    pub fn max_request_header_len(mut self, len: usize) -> Self {
+        self.max_reuest_header_len = len;
+        self
+    }
+
+    /// maximum length of the request body in bytes
+    pub fn max_body_size(mut self, size: usize) -> Self {
+        self.max_body_size = size;
+        self
+    }
+
+    /// maximum number of request headers
+    pub fn max_headers(mut self, count: usize) -> Self {
+        self.max_headers = count;
+        self
+    }
+}
+
 pub struct HttpCodec {
     config: HttpCodecCfg,
     router: Arc<InternalRouter>,
     request: Option<PartialResultWithBody>,
+    remote_addr: Option<SocketAddr>,
+    peer_credentials: Option<PeerCredentials>,
+    peer_certificate: Option<String>,
+    /// keeps the connection counted in the metrics until the codec is dropped
+    connection: Option<ConnectionGuard>,
 }
 
 struct PartialResultWithBody {
@@ -90,21 +158,37 @@ type BodyLength = usize;
 
 impl Default for HttpCodec {
     fn default() -> Self {
-        HttpCodec { config: HttpCodecCfg::default(), router: Arc::new(InternalRouter::new(::router::Router::new())), request: None }
+        HttpCodec { config: HttpCodecCfg::default(), router: Arc::new(InternalRouter::new(::router::Router::new())), request: None, remote_addr: None, peer_credentials: None, peer_certificate: None, connection: None }
     }
 }
 
 pub enum DecodingResult {
-    RouteNotFound,
-    HeaderTooLarge,
-    BodyTooLarge,
+    RouteNotFound(RejectedRequest),
+    HeaderTooLarge(RejectedRequest),
+    BodyTooLarge(RejectedRequest),
+    UnsupportedMediaType(RejectedRequest),
     Ok(DecodedRequest),
 }
 
+/// Information about a request rejected by the codec, used for logging
+#[derive(Debug)]
+pub struct RejectedRequest {
+    pub method: Option<Method>,
+    pub uri: Option<Uri>,
+    pub version: Version,
+    pub headers: HeaderMap<HeaderValue>,
+    pub remote_addr: Option<SocketAddr>,
+    /// path of the matched route, if the request was rejected because of its limits
+    pub route: Option<String>,
+}
+
 pub struct DecodedRequest {
     pub request: Request<Body>,
     pub route: Arc<Route>,
     pub params: Params,
+    pub remote_addr: Option<SocketAddr>,
+    pub peer_credentials: Option<PeerCredentials>,
+    pub peer_certificate: Option<String>,
 }
 
 impl ::std::fmt::Debug for DecodingResult {
@@ -112,9 +196,10 @@ impl ::std::fmt::Debug for DecodingResult {
         use self::DecodingResult::*;
 
         match *self {
-            RouteNotFound => write!(f, "RouteNotFound"),
-            HeaderTooLarge => write!(f, "HeaderTooLarge"),
-            BodyTooLarge => write!(f, "BodyTooLarge"),
+            RouteNotFound(ref rej) => write!(f, "RouteNotFound({:?} {:?})", rej.method, rej.uri),
+            HeaderTooLarge(_) => write!(f, "HeaderTooLarge"),
+            BodyTooLarge(ref rej) => write!(f, "BodyTooLarge({:?} {:?})", rej.method, rej.uri),
+            UnsupportedMediaType(ref rej) => write!(f, "UnsupportedMediaType({:?} {:?})", rej.method, rej.uri),
             Ok(ref res) => write!(f, "Ok({:?} [{:?}])", res.request, res.params),
         }
     }
@@ -137,7 +222,7 @@ impl Decoder for HttpCodec {
             if let Some(partial) = o {
                 trace!("Completed partial body, returning");
                 let PartialResultWithBody { body_length: _, request, handler: route, params } = partial;
-                let dec_req = DecodedRequest { request, params, route };
+                let dec_req = DecodedRequest { request, params, route, remote_addr: self.remote_addr, peer_credentials: self.peer_credentials, peer_certificate: self.peer_certificate.clone() };
                 let decoding_result = DecodingResult::Ok(dec_req);
                 return Ok(Some(decoding_result));
             }
@@ -149,7 +234,8 @@ impl Decoder for HttpCodec {
             if buf.len() > self.config.max_reuest_header_len {
                 buf.clear();
                 trace!("Header exceeds limit, will return error");
-                return Ok(Some(DecodingResult::HeaderTooLarge));
+                let rejected = RejectedRequest { method: None, uri: None, version: Version::HTTP_11, headers: HeaderMap::new(), remote_addr: self.remote_addr, route: None };
+                return Ok(Some(DecodingResult::HeaderTooLarge(rejected)));
             } else {
                 trace!("Not enough data for header");
                 return Ok(None);
@@ -158,15 +244,28 @@ impl Decoder for HttpCodec {
         let (method, uri, version, header_map, body_complete, body_start, body_length) = result.unwrap();
         buf.split_to(body_start);//remove part of buffer
 
-        if body_length > self.config.max_body_size {
+        let o = self.router.resolve(&method, uri.path());
+        let max_body_size = o.as_ref().and_then(|&(ref route, _)| route.get_limits().max_body_size).unwrap_or(self.config.max_body_size);
+        if body_length > max_body_size {
             buf.clear();
             trace!("Body exceeds limit, will return error");
-            return Ok(Some(DecodingResult::BodyTooLarge));
+            let route = o.map(|(route, _)| route.path.clone());
+            let rejected = RejectedRequest { method: Some(method), uri: Some(uri), version, headers: header_map, remote_addr: self.remote_addr, route };
+            return Ok(Some(DecodingResult::BodyTooLarge(rejected)));
         }
 
-        let o = self.router.resolve(&method, uri.path());
         if let Some((route, params)) = o {
             trace!("Found route for {} {}", &method, uri.path());
+            if let Err(violation) = route.get_limits().check(body_start, &header_map, body_length) {
+                buf.clear();
+                trace!("Request violates route limits: {:?}", violation);
+                let rejected = RejectedRequest { method: Some(method), uri: Some(uri), version, headers: header_map, remote_addr: self.remote_addr, route: Some(route.path.clone()) };
+                return Ok(Some(match violation {
+                    LimitViolation::Body => DecodingResult::BodyTooLarge(rejected),
+                    LimitViolation::Header => DecodingResult::HeaderTooLarge(rejected),
+                    LimitViolation::ContentType => DecodingResult::UnsupportedMediaType(rejected),
+                }));
+            }
             let mut b = RequestBuilder::new();
             b.method(method);
             b.uri(uri);
@@ -186,7 +285,7 @@ impl Decoder for HttpCodec {
                 let body = get_body(buf, 0, body_length);
                 *request.body_mut() = body;
                 debug!("Got Request: {:?}", request);
-                let dec_req = DecodedRequest { request, params: params.into(), route };
+                let dec_req = DecodedRequest { request, params: params.into(), route, remote_addr: self.remote_addr, peer_credentials: self.peer_credentials, peer_certificate: self.peer_certificate.clone() };
                 let decoding_result = DecodingResult::Ok(dec_req);
                 Ok(Some(decoding_result))
             } else {
@@ -197,14 +296,15 @@ impl Decoder for HttpCodec {
             }
         } else {
             buf.clear();
-            Ok(Some(DecodingResult::RouteNotFound))
+            let rejected = RejectedRequest { method: Some(method), uri: Some(uri), version, headers: header_map, remote_addr: self.remote_addr, route: None };
+            Ok(Some(DecodingResult::RouteNotFound(rejected)))
         }
     }
 }
 
 fn get_body(buf: &mut BytesMut, content_start: usize, content_length: usize) -> Body {
     if content_length > 0 {
-        println!("Contentlength={}, contentstart={}, buf.len={}", content_length, content_start, buf.len());
+        trace!("Contentlength={}, contentstart={}, buf.len={}", content_length, content_start, buf.len());
         let split = buf.split_off(content_start);
         let v: Vec<u8> = Vec::from(split.as_ref());
         Body(Some(v))
@@ -347,7 +447,7 @@ mod tests {
         let r = parse(bytes, config);
 
         match r {
-            DecodingResult::HeaderTooLarge => return,
+            DecodingResult::HeaderTooLarge(_) => return,
             r => panic!("wrong return value {:?}", r)
         }
     }
@@ -362,14 +462,14 @@ mod tests {
         let r = parse(bytes, config);
 
         match r {
-            DecodingResult::BodyTooLarge => return,
+            DecodingResult::BodyTooLarge(_) => return,
             r => panic!("wrong return value {:?}", r)
         }
     }
 
     fn parse(mut bytes: BytesMut, config: HttpCodecCfg) -> DecodingResult {
         let router = Arc::new(InternalRouter::new(Router::new()));
-        let mut codec = HttpCodec { config, router, request: None };
+        let mut codec = HttpCodec { config, router, request: None, remote_addr: None, peer_credentials: None, peer_certificate: None, connection: None };
         let r = codec.decode(&mut bytes);
         match r {
             Ok(s) => match s {
@@ -380,6 +480,38 @@ mod tests {
         }
     }
 
+    #[test]
+    fn route_limits() {
+        let mut r = Router::new();
+        r.post("/upload", handle).max_body_size(100);
+        r.post("/login", handle).max_body_size(4).content_types(vec!["application/json"]);
+        let router = Arc::new(InternalRouter::new(r));
+        let config = HttpCodecCfg::new().max_body_size(10);
+
+        let decode = |head: &[u8]| {
+            let mut codec = HttpCodec { config, router: router.clone(), request: None, remote_addr: None, peer_credentials: None, peer_certificate: None, connection: None };
+            let mut bytes = BytesMut::from(head);
+            codec.decode(&mut bytes).unwrap()
+        };
+        assert_that(&decode(b"POST /upload HTTP/1.1\r\nContent-Length: 50\r\n\r\n")).is_none();
+        match decode(b"POST /upload HTTP/1.1\r\nContent-Length: 101\r\n\r\n") {
+            Some(DecodingResult::BodyTooLarge(_)) => {}
+            r => panic!("wrong return value {:?}", r)
+        }
+        match decode(b"POST /login HTTP/1.1\r\nContent-Length: 5\r\nContent-Type: application/json\r\n\r\n") {
+            Some(DecodingResult::BodyTooLarge(_)) => {}
+            r => panic!("wrong return value {:?}", r)
+        }
+        match decode(b"POST /login HTTP/1.1\r\nContent-Length: 2\r\nContent-Type: text/plain\r\n\r\n{}") {
+            Some(DecodingResult::UnsupportedMediaType(_)) => {}
+            r => panic!("wrong return value {:?}", r)
+        }
+        match decode(b"POST /login HTTP/1.1\r\nContent-Length: 2\r\nContent-Type: application/json\r\n\r\n{}") {
+            Some(DecodingResult::Ok(_)) => {}
+            r => panic!("wrong return value {:?}", r)
+        }
+    }
+
     #[test]
     fn test_body_missing() {
         let mut bytes = BytesMut::from(RAW_GET.as_ref());
@@ -389,7 +521,7 @@ mod tests {
         let mut r = Router::new();
         r.get("/", handle);
         let cfg = HttpCodecCfg::default();
-        let mut codec = HttpCodec { config: cfg, router: Arc::new(InternalRouter::new(r)), request: None };
+        let mut codec = HttpCodec { config: cfg, router: Arc::new(InternalRouter::new(r)), request: None, remote_addr: None, peer_credentials: None, peer_certificate: None, connection: None };
         let r = codec.decode(&mut bytes);
         assert_that(&r).is_ok();
         assert_that(&r.unwrap()).is_none();
@@ -409,7 +541,7 @@ mod tests {
         let r = r.unwrap();
 
         match r {
-            DecodingResult::RouteNotFound => return,
+            DecodingResult::RouteNotFound(_) => return,
             r => panic!("wrong return value {:?}", r)
         }
     }
@@ -430,7 +562,7 @@ mod tests {
         let mut r = Router::new();
         r.get("/", handle);
 
-        let mut codec = HttpCodec { config: HttpCodecCfg::default(), router: Arc::new(InternalRouter::new(r)), request: None };
+        let mut codec = HttpCodec { config: HttpCodecCfg::default(), router: Arc::new(InternalRouter::new(r)), request: None, remote_addr: None, peer_credentials: None, peer_certificate: None, connection: None };
 
         let mut bytes = BytesMut::from(RAW_GET.as_ref());
         bytes.extend_from_slice(RAW_HEADER.as_ref());
@@ -470,7 +602,7 @@ mod tests {
         let mut r = Router::new();
         r.get("/", handle);
 
-        let mut codec = HttpCodec { config: HttpCodecCfg::default(), router: Arc::new(InternalRouter::new(r)), request: None };
+        let mut codec = HttpCodec { config: HttpCodecCfg::default(), router: Arc::new(InternalRouter::new(r)), request: None, remote_addr: None, peer_credentials: None, peer_certificate: None, connection: None };
 
         let mut bytes = BytesMut::from(RAW_GET.as_ref());
         bytes.extend_from_slice(RAW_HEADER.as_ref());
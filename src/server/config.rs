@@ -0,0 +1,547 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Declarative server configuration loaded from TOML or JSON files and environment variables.
+//!
+//! Environment variables override the file, ```RIR_THREADS=8``` sets ```threads```
+//! and nested keys are separated by a double underscore, eg. ```RIR_CODEC__MAX_BODY_SIZE=1000```.
+//! Values are parsed as json if possible, so lists can be given like ```RIR_LISTENERS='[{"address": "0.0.0.0:80"}]'```.
+//!
+//! The only configurable timeout is ```shutdown_grace_period_secs```. The server has no request read or
+//! keep-alive idle timeout, slow or idle clients keep their connection until they close it,
+//! so put the server behind a reverse proxy enforcing those timeouts.
+//!
+//! ```toml
+//! address = "0.0.0.0:443"
+//! threads = 8
+//! shutdown_grace_period_secs = 10
+//!
+//! [tls]
+//! cert = "cert.pem"
+//! key = "key.pem"
+//! reload_interval_secs = 60
+//!
+//! [codec]
+//! max_body_size = 1000000
+//!
+//! [[listeners]]
+//! address = "0.0.0.0:80"
+//! redirect_https_port = 443
+//!
+//! [[static_files]]
+//! url = "/assets"
+//! path = "public"
+//! cache = "file_info_change"
+//!
+//! [[redirects]]
+//! from = "/old"
+//! to = "/new"
+//! permanent = true
+//! ```
+//!
+//! ```rust,no_run
+//! # use rest_in_rust::*;
+//! use rest_in_rust::server::config::ServerConfig;
+//!
+//! let config = ServerConfig::load(Some("server.toml"), "RIR").unwrap();
+//! let server = config.build(Router::new()).unwrap();
+//! server.start().unwrap();
+//! ```
+
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use http::StatusCode;
+use http::header::{HeaderValue, LOCATION};
+use serde_json::{self, Map, Value};
+
+use ::request::Request;
+use ::response::Response;
+use ::router::{ChangeDetection, EvictionPolicy, Router};
+use super::{HttpsRedirect, ListenAddr, ListenerMode, Server, ServerHandle};
+use super::codec::HttpCodecCfg;
+use super::tls::{ClientAuth, Identity, TlsConfig};
+use super::tlsreload::ReloadableTls;
+#[cfg(unix)]
+use super::unix::UnixSocket;
+
+/// Configuration of a server, see the module documentation for the format
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// main address, a socket address, ```unix:/path/to/socket``` or ```fd:3```
+    pub address: String,
+    /// serves https on the main address if set
+    pub tls: Option<TlsFiles>,
+    /// size of the worker thread pool
+    pub threads: usize,
+    /// time in flight requests may take to finish after the server was stopped.
+    /// There are no read or idle timeouts, see the module documentation
+    pub shutdown_grace_period_secs: u64,
+    pub codec: CodecConfig,
+    /// additional http, https or redirect listeners
+    pub listeners: Vec<ListenerConfig>,
+    pub static_files: Vec<StaticMount>,
+    pub redirects: Vec<RedirectConfig>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            address: "127.0.0.1:8080".to_string(),
+            tls: None,
+            threads: 20,
+            shutdown_grace_period_secs: 30,
+            codec: CodecConfig::default(),
+            listeners: Vec::new(),
+            static_files: Vec::new(),
+            redirects: Vec::new(),
+        }
+    }
+}
+
+/// Limits of the http parser, see ```HttpCodecCfg```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CodecConfig {
+    pub max_request_header_len: usize,
+    pub max_body_size: usize,
+    pub max_headers: usize,
+}
+
+impl Default for CodecConfig {
+    fn default() -> Self {
+        CodecConfig { max_request_header_len: 8000, max_body_size: 20_000_000, max_headers: 64 }
+    }
+}
+
+/// Pem encoded certificates of a https listener, they are loaded again on every reload
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    #[serde(default)]
+    pub sni: Vec<SniFiles>,
+    #[serde(default)]
+    pub client_auth: Option<ClientAuthFiles>,
+    /// reloads when one of the files changed, checked at most once per interval
+    #[serde(default)]
+    pub reload_interval_secs: Option<u64>,
+    #[serde(default)]
+    pub reload_on_sighup: bool,
+}
+
+/// Certificate used for connections requesting the given server name
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SniFiles {
+    pub name: String,
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+/// Verification of client certificates against a pem encoded ca bundle
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClientAuthFiles {
+    pub ca: PathBuf,
+    /// refuses connections without a client certificate
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// Additional listener, serving https if ```tls``` is set or redirecting to https if ```redirect_https_port``` is set
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ListenerConfig {
+    pub address: String,
+    #[serde(default)]
+    pub tls: Option<TlsFiles>,
+    #[serde(default)]
+    pub redirect_https_port: Option<u16>,
+}
+
+/// Static file or directory served below an url path
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StaticMount {
+    pub url: String,
+    pub path: PathBuf,
+    #[serde(default)]
+    pub cache: StaticCache,
+}
+
+/// Caching of static files, see ```ChangeDetection```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StaticCache {
+    NoCache,
+    FileInfoChange,
+    Never,
+    /// reads the file again if the cache entry is older than the given seconds
+    TimedSecs(u64),
+}
+
+impl Default for StaticCache {
+    fn default() -> Self {
+        StaticCache::NoCache
+    }
+}
+
+/// Redirects GET requests of an url path to another location
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RedirectConfig {
+    pub from: String,
+    pub to: String,
+    /// uses 308 instead of 307
+    #[serde(default)]
+    pub permanent: bool,
+}
+
+/// Invalid configuration, ```key``` is the path of the bad entry like ```listeners[0].address```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    pub key: String,
+    pub message: String,
+}
+
+impl ConfigError {
+    fn new<K: Into<String>, M: Into<String>>(key: K, message: M) -> Self {
+        ConfigError { key: key.into(), message: message.into() }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.key.is_empty() {
+            write!(f, "Invalid server configuration: {}", self.message)
+        } else {
+            write!(f, "Invalid server configuration at {}: {}", self.key, self.message)
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+impl From<ConfigError> for io::Error {
+    fn from(e: ConfigError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, e.to_string())
+    }
+}
+
+impl ServerConfig {
+    /// reads the file, if given, and applies the environment variables starting with ```<env_prefix>_```.
+    /// Files ending with ```.json``` are parsed as json, all others as toml
+    pub fn load<P: AsRef<Path>>(file: Option<P>, env_prefix: &str) -> Result<Self, ConfigError> {
+        let mut value = match file {
+            Some(file) => read_file(file.as_ref())?,
+            None => Value::Object(Map::new()),
+        };
+        overlay_env(&mut value, env_prefix, env::vars())?;
+        ServerConfig::from_value(value)
+    }
+
+    pub fn from_toml(toml: &str) -> Result<Self, ConfigError> {
+        ServerConfig::from_value(parse_toml(toml)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, ConfigError> {
+        ServerConfig::from_value(parse_json(json)?)
+    }
+
+    /// configuration from environment variables only
+    pub fn from_env(env_prefix: &str) -> Result<Self, ConfigError> {
+        ServerConfig::load(None::<&Path>, env_prefix)
+    }
+
+    fn from_value(value: Value) -> Result<Self, ConfigError> {
+        ::serde_path_to_error::deserialize(value).map_err(|e| {
+            let key = e.path().to_string();
+            let key = if key == "." { String::new() } else { key };
+            ConfigError::new(key, e.into_inner().to_string())
+        })
+    }
+
+    /// checks the values which can not be expressed by the types
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        listen_addr("address", &self.address)?;
+        if self.threads == 0 {
+            return Err(ConfigError::new("threads", "at least one thread is required"));
+        }
+        self.codec.validate()?;
+        for (i, listener) in self.listeners.iter().enumerate() {
+            listen_addr(&format!("listeners[{}].address", i), &listener.address)?;
+            if listener.tls.is_some() && listener.redirect_https_port.is_some() {
+                return Err(ConfigError::new(format!("listeners[{}]", i), "tls and redirect_https_port can not be combined"));
+            }
+        }
+        for (i, mount) in self.static_files.iter().enumerate() {
+            if !mount.url.starts_with('/') {
+                return Err(ConfigError::new(format!("static_files[{}].url", i), "has to start with /"));
+            }
+            if !mount.path.exists() {
+                return Err(ConfigError::new(format!("static_files[{}].path", i), format!("{:?} does not exist", mount.path)));
+            }
+        }
+        for (i, redirect) in self.redirects.iter().enumerate() {
+            if !redirect.from.starts_with('/') {
+                return Err(ConfigError::new(format!("redirects[{}].from", i), "has to start with /"));
+            }
+            if HeaderValue::from_str(&redirect.to).is_err() {
+                return Err(ConfigError::new(format!("redirects[{}].to", i), "is not a valid location"));
+            }
+        }
+        Ok(())
+    }
+
+    /// validates the configuration, loads the certificates and registers the static files and redirects at the router
+    pub fn build(self, mut router: Router) -> Result<ConfiguredServer, ConfigError> {
+        self.validate()?;
+        for mount in self.static_files {
+            let (change_detection, eviction) = mount.cache.policies();
+            router.static_path_cached(mount.url, mount.path, change_detection, eviction);
+        }
+        for redirect in self.redirects {
+            let status = if redirect.permanent { StatusCode::PERMANENT_REDIRECT } else { StatusCode::TEMPORARY_REDIRECT };
+            let location = HeaderValue::from_str(&redirect.to).expect("validated");
+            router.get(redirect.from, move |_: &mut Request| {
+                Response::builder().status(status).header(LOCATION, location.clone()).build()
+            });
+        }
+
+        let tls = match self.tls {
+            Some(ref tls) => Some(tls.load("tls")?),
+            None => None,
+        };
+        let mut server = Server::listen(listen_addr("address", &self.address)?, router);
+        server.set_thread_pool_size(self.threads);
+        server.set_shutdown_grace_period(Duration::from_secs(self.shutdown_grace_period_secs));
+        server.set_codec_cfg(self.codec.into());
+        for (i, listener) in self.listeners.into_iter().enumerate() {
+            let addr = listen_addr(&format!("listeners[{}].address", i), &listener.address)?;
+            let mode = match (listener.tls, listener.redirect_https_port) {
+                (Some(ref tls), _) => ListenerMode::Https(tls.load(&format!("listeners[{}].tls", i))?),
+                (None, Some(port)) => ListenerMode::Redirect(Arc::new(HttpsRedirect::new(port))),
+                (None, None) => ListenerMode::Http,
+            };
+            server.listeners.push((addr, mode));
+        }
+        Ok(ConfiguredServer { server, tls })
+    }
+}
+
+impl CodecConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        let limits = [("max_request_header_len", self.max_request_header_len), ("max_body_size", self.max_body_size), ("max_headers", self.max_headers)];
+        match limits.iter().find(|&&(_, value)| value == 0) {
+            Some(&(key, _)) => Err(ConfigError::new(format!("codec.{}", key), "has to be greater than 0")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl From<CodecConfig> for HttpCodecCfg {
+    fn from(config: CodecConfig) -> Self {
+        HttpCodecCfg::new()
+            .max_request_header_len(config.max_request_header_len)
+            .max_body_size(config.max_body_size)
+            .max_headers(config.max_headers)
+    }
+}
+
+impl TlsFiles {
+    /// reads the certificates, the files are read again on every reload
+    pub fn tls_config(&self) -> io::Result<TlsConfig> {
+        let mut config = TlsConfig::new(Identity::from_pem_files(&self.cert, &self.key)?);
+        for sni in self.sni.iter() {
+            config = config.sni(sni.name.as_str(), Identity::from_pem_files(&sni.cert, &sni.key)?);
+        }
+        if let Some(ref client_auth) = self.client_auth {
+            config = config.client_auth(if client_auth.required {
+                ClientAuth::Required(client_auth.ca.clone())
+            } else {
+                ClientAuth::Optional(client_auth.ca.clone())
+            });
+        }
+        Ok(config)
+    }
+
+    fn load(&self, key: &str) -> Result<ReloadableTls, ConfigError> {
+        let files = self.clone();
+        let tls = ReloadableTls::new(move || files.tls_config()).map_err(|e| ConfigError::new(key, e.to_string()))?;
+        if let Some(interval) = self.reload_interval_secs {
+            let mut watched = vec![self.cert.clone(), self.key.clone()];
+            for sni in self.sni.iter() {
+                watched.push(sni.cert.clone());
+                watched.push(sni.key.clone());
+            }
+            tls.watch_files(watched, Duration::from_secs(interval));
+        }
+        if self.reload_on_sighup {
+            #[cfg(unix)]
+            tls.reload_on_sighup().map_err(|e| ConfigError::new(format!("{}.reload_on_sighup", key), e.to_string()))?;
+            #[cfg(not(unix))]
+            return Err(ConfigError::new(format!("{}.reload_on_sighup", key), "only supported on unix"));
+        }
+        Ok(tls)
+    }
+}
+
+impl StaticCache {
+    fn policies(&self) -> (ChangeDetection, EvictionPolicy) {
+        match *self {
+            StaticCache::NoCache => (ChangeDetection::NoCache, EvictionPolicy::Never),
+            StaticCache::FileInfoChange => (ChangeDetection::FileInfoChange, EvictionPolicy::default()),
+            StaticCache::Never => (ChangeDetection::Never, EvictionPolicy::default()),
+            StaticCache::TimedSecs(secs) => (ChangeDetection::Timed(Duration::from_secs(secs)), EvictionPolicy::default()),
+        }
+    }
+}
+
+/// Server built from a ```ServerConfig```, serves https on the main address if tls was configured.
+/// Dereferences to the ```Server``` to add state, access logging etc.
+pub struct ConfiguredServer {
+    server: Server,
+    tls: Option<ReloadableTls>,
+}
+
+impl ConfiguredServer {
+    /// serves until the server is stopped via the ```ServerStopper```
+    pub fn start(self) -> io::Result<()> {
+        self.server.serve(ConfiguredServer::mode(self.tls))
+    }
+
+    /// binds all sockets and serves in a new thread
+    pub fn start_non_blocking(self) -> io::Result<ServerHandle> {
+        self.server.spawn(ConfiguredServer::mode(self.tls))
+    }
+
+    pub fn into_inner(self) -> (Server, Option<ReloadableTls>) {
+        (self.server, self.tls)
+    }
+
+    fn mode(tls: Option<ReloadableTls>) -> ListenerMode {
+        match tls {
+            Some(tls) => ListenerMode::Https(tls),
+            None => ListenerMode::Http,
+        }
+    }
+}
+
+impl Deref for ConfiguredServer {
+    type Target = Server;
+
+    fn deref(&self) -> &Server {
+        &self.server
+    }
+}
+
+impl DerefMut for ConfiguredServer {
+    fn deref_mut(&mut self) -> &mut Server {
+        &mut self.server
+    }
+}
+
+fn listen_addr(key: &str, addr: &str) -> Result<ListenAddr, ConfigError> {
+    #[cfg(unix)]
+    {
+        if addr.starts_with("unix:") {
+            return Ok(ListenAddr::Unix(UnixSocket::new(&addr[5..])));
+        }
+        if addr.starts_with("fd:") {
+            return addr[3..].parse().map(ListenAddr::Fd).map_err(|_| ConfigError::new(key, format!("invalid file descriptor {:?}", addr)));
+        }
+    }
+    addr.parse::<SocketAddr>().map(ListenAddr::Tcp).map_err(|e| ConfigError::new(key, format!("invalid address {:?}: {}", addr, e)))
+}
+
+fn read_file(path: &Path) -> Result<Value, ConfigError> {
+    let content = fs::read_to_string(path).map_err(|e| ConfigError::new("", format!("Could not read {:?}: {}", path, e)))?;
+    if path.extension().map(|e| e == "json").unwrap_or(false) {
+        parse_json(&content)
+    } else {
+        parse_toml(&content)
+    }
+}
+
+fn parse_toml(toml: &str) -> Result<Value, ConfigError> {
+    ::toml::from_str(toml).map_err(|e| ConfigError::new("", e.to_string()))
+}
+
+fn parse_json(json: &str) -> Result<Value, ConfigError> {
+    serde_json::from_str(json).map_err(|e| ConfigError::new("", e.to_string()))
+}
+
+/// sets ```PREFIX_A__B=value``` at the key ```a.b```
+fn overlay_env<I: Iterator<Item=(String, String)>>(value: &mut Value, prefix: &str, vars: I) -> Result<(), ConfigError> {
+    let prefix = format!("{}_", prefix);
+    for (name, raw) in vars {
+        if !name.starts_with(&prefix) {
+            continue;
+        }
+        let keys: Vec<String> = name[prefix.len()..].split("__").map(|k| k.to_lowercase()).collect();
+        let parsed = serde_json::from_str(&raw).ok();
+        let parsed = parsed.unwrap_or_else(|| Value::String(raw));
+        let mut current = &mut *value;
+        for (i, key) in keys.iter().enumerate() {
+            current = match *{ current } {
+                Value::Object(ref mut object) => object.entry(key.clone()).or_insert_with(|| Value::Object(Map::new())),
+                _ => return Err(ConfigError::new(keys[..i].join("."), format!("{} can not override a value which is not a table", name))),
+            };
+        }
+        *current = parsed;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml_with_env() {
+        let mut value = parse_toml("address = \"0.0.0.0:443\"\n[codec]\nmax_body_size = 100\n[[listeners]]\naddress = \"0.0.0.0:80\"\nredirect_https_port = 443").unwrap();
+        let vars = vec![("RIR_THREADS".to_string(), "4".to_string()), ("RIR_CODEC__MAX_HEADERS".to_string(), "10".to_string()),
+                        ("OTHER_THREADS".to_string(), "2".to_string())];
+        overlay_env(&mut value, "RIR", vars.into_iter()).unwrap();
+        let config = ServerConfig::from_value(value).unwrap();
+
+        assert_eq!("0.0.0.0:443", config.address);
+        assert_eq!(4, config.threads);
+        assert_eq!(CodecConfig { max_request_header_len: 8000, max_body_size: 100, max_headers: 10 }, config.codec);
+        assert_eq!(Some(443), config.listeners[0].redirect_https_port);
+        assert_eq!(Ok(()), config.validate());
+    }
+
+    #[test]
+    fn errors_point_at_key() {
+        let error = ServerConfig::from_json(r#"{"listeners": [{"address": 80}]}"#).unwrap_err();
+        assert_eq!("listeners[0].address", error.key);
+        let error = ServerConfig::from_toml("[codec]\nmax_headers = \"many\"").unwrap_err();
+        assert_eq!("codec.max_headers", error.key);
+
+        let config = ServerConfig::from_toml("[[listeners]]\naddress = \"localhost\"").unwrap();
+        assert_eq!("listeners[0].address", config.validate().unwrap_err().key);
+        let config = ServerConfig::from_toml("[codec]\nmax_body_size = 0").unwrap();
+        assert_eq!("codec.max_body_size", config.validate().unwrap_err().key);
+    }
+}
@@ -0,0 +1,90 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::cell::Cell;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::mpsc::Receiver;
+use std::thread::JoinHandle;
+
+use super::{ListenAddr, ServerStopper};
+
+/// Handle of a server running in its own thread, see ```Server::start_http_non_blocking```
+///
+/// ```rust,no_run
+/// # use rest_in_rust::*;
+/// let s = Server::new("127.0.0.1:0".parse().unwrap(), Router::new());
+/// let handle = s.start_http_non_blocking().unwrap();
+/// handle.wait_ready().unwrap();
+/// println!("Listening on {}", handle.local_addr().unwrap());
+///
+/// handle.stop();
+/// handle.join().unwrap();
+/// ```
+pub struct ServerHandle {
+    local_addrs: Vec<ListenAddr>,
+    stopper: ServerStopper,
+    ready: Receiver<()>,
+    is_ready: Cell<bool>,
+    thread: JoinHandle<io::Result<()>>,
+}
+
+impl ServerHandle {
+    pub(crate) fn new(local_addrs: Vec<ListenAddr>, stopper: ServerStopper, ready: Receiver<()>, thread: JoinHandle<io::Result<()>>) -> Self {
+        ServerHandle { local_addrs, stopper, ready, is_ready: Cell::new(false), thread }
+    }
+
+    /// returns the tcp address the server is bound to, useful when binding to port 0.
+    /// Returns ```None``` for unix domain sockets
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match *self.listen_addr() {
+            ListenAddr::Tcp(addr) => Some(addr),
+            #[cfg(unix)]
+            ListenAddr::Unix(_) | ListenAddr::Fd(_) => None,
+        }
+    }
+
+    /// returns the address the server is bound to
+    pub fn listen_addr(&self) -> &ListenAddr {
+        &self.local_addrs[0]
+    }
+
+    /// returns the addresses of all listeners, starting with the one passed to ```Server::new```
+    pub fn listen_addrs(&self) -> &[ListenAddr] {
+        &self.local_addrs
+    }
+
+    /// returns the stopper of the server
+    pub fn stopper(&self) -> &ServerStopper {
+        &self.stopper
+    }
+
+    /// triggers the graceful shutdown of the server
+    pub fn stop(&self) {
+        self.stopper.stop();
+    }
+
+    /// blocks until the server accepts connections.
+    /// Returns an error if the server thread terminated before, use ```join``` to get its error.
+    pub fn wait_ready(&self) -> io::Result<()> {
+        if !self.is_ready.get() {
+            self.ready.recv().map_err(|_| io::Error::new(io::ErrorKind::Other, "Server terminated before it was ready"))?;
+            self.is_ready.set(true);
+        }
+        Ok(())
+    }
+
+    /// blocks until the server thread terminated
+    pub fn join(self) -> io::Result<()> {
+        match self.thread.join() {
+            Ok(result) => result,
+            Err(_) => Err(io::Error::new(io::ErrorKind::Other, "Server thread panicked")),
+        }
+    }
+}
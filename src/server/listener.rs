@@ -0,0 +1,340 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Accept loop replacing ```tokio_proto::TcpServer``` which can not be stopped.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener as StdTcpListener};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+use futures::{Future, Poll, Stream};
+use futures::future::select_all;
+use http::Response;
+use tokio_core::net::TcpListener;
+use tokio_core::reactor::{Core, Handle};
+use tokio_proto::BindServer;
+use tokio_proto::pipeline::Pipeline;
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use ::body::Body;
+use super::{InternalServer, ServerStopper};
+use super::codec::{DecodingResult, Http, PeerAddr};
+use super::unix::PeerCredentials;
+use super::tlsreload::TlsProto;
+#[cfg(unix)]
+use std::os::unix::io::RawFd;
+#[cfg(unix)]
+use super::unix::{UnixSocket, socket_family};
+
+/// Address a server listens on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(UnixSocket),
+    /// an already listening tcp or unix socket, eg. inherited from the parent process.
+    /// The server takes ownership of the file descriptor
+    #[cfg(unix)]
+    Fd(RawFd),
+}
+
+impl ListenAddr {
+    pub(crate) fn bind(&self) -> io::Result<Bound> {
+        match *self {
+            ListenAddr::Tcp(ref addr) => Ok(Bound::Tcp(StdTcpListener::bind(addr)?)),
+            #[cfg(unix)]
+            ListenAddr::Unix(ref socket) => Ok(Bound::Unix(socket.bind()?, socket.clone())),
+            #[cfg(unix)]
+            ListenAddr::Fd(fd) => Bound::from_fd(fd),
+        }
+    }
+}
+
+impl From<SocketAddr> for ListenAddr {
+    fn from(addr: SocketAddr) -> Self {
+        ListenAddr::Tcp(addr)
+    }
+}
+
+#[cfg(unix)]
+impl From<UnixSocket> for ListenAddr {
+    fn from(socket: UnixSocket) -> Self {
+        ListenAddr::Unix(socket)
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ListenAddr::Tcp(ref addr) => write!(f, "{}", addr),
+            #[cfg(unix)]
+            ListenAddr::Unix(ref socket) => write!(f, "unix:{}", socket.path().display()),
+            #[cfg(unix)]
+            ListenAddr::Fd(fd) => write!(f, "fd:{}", fd),
+        }
+    }
+}
+
+/// a bound but not yet served listener
+pub enum Bound {
+    Tcp(StdTcpListener),
+    #[cfg(unix)]
+    Unix(::std::os::unix::net::UnixListener, UnixSocket),
+    /// inherited unix socket, the socket file is not owned by the server
+    #[cfg(unix)]
+    UnixFd(::std::os::unix::net::UnixListener, RawFd),
+}
+
+impl Bound {
+    /// the actual address, eg. with the port assigned by the os when binding to port 0
+    pub fn local_addr(&self) -> io::Result<ListenAddr> {
+        match *self {
+            Bound::Tcp(ref listener) => Ok(ListenAddr::Tcp(listener.local_addr()?)),
+            #[cfg(unix)]
+            Bound::Unix(_, ref socket) => Ok(ListenAddr::Unix(socket.clone())),
+            #[cfg(unix)]
+            Bound::UnixFd(_, fd) => Ok(ListenAddr::Fd(fd)),
+        }
+    }
+
+    #[cfg(unix)]
+    fn from_fd(fd: RawFd) -> io::Result<Bound> {
+        use std::os::unix::io::FromRawFd;
+
+        match socket_family(fd)? {
+            ::libc::AF_UNIX => Ok(Bound::UnixFd(unsafe { ::std::os::unix::net::UnixListener::from_raw_fd(fd) }, fd)),
+            ::libc::AF_INET | ::libc::AF_INET6 => Ok(Bound::Tcp(unsafe { StdTcpListener::from_raw_fd(fd) })),
+            family => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("File descriptor {} has unsupported address family {}", fd, family))),
+        }
+    }
+
+    fn accept(self, protocol: Protocol, service: InternalServer, handle: &Handle, unflushed: &Arc<AtomicUsize>) -> io::Result<Box<Future<Item=(), Error=io::Error>>> {
+        let unflushed = unflushed.clone();
+        match self {
+            Bound::Tcp(listener) => {
+                let addr = listener.local_addr()?;
+                let listener = TcpListener::from_listener(listener, &addr, handle)?;
+                Ok(protocol.accept(listener.incoming().map(move |(socket, _)| Tracked::new(socket, unflushed.clone())), service, handle.clone()))
+            }
+            #[cfg(unix)]
+            Bound::Unix(listener, _) | Bound::UnixFd(listener, _) => {
+                let listener = ::tokio_uds::UnixListener::from_listener(listener, handle)?;
+                Ok(protocol.accept(listener.incoming().map(move |(socket, _)| Tracked::new(socket, unflushed.clone())), service, handle.clone()))
+            }
+        }
+    }
+}
+
+/// protocol spoken on a listener
+pub enum Protocol {
+    Http(Http),
+    Https(TlsProto<Http>),
+}
+
+impl Protocol {
+    fn accept<S>(self, incoming: S, service: InternalServer, handle: Handle) -> Box<Future<Item=(), Error=io::Error>>
+        where S: Stream<Error=io::Error> + 'static,
+              S::Item: AsyncRead + AsyncWrite + PeerAddr + 'static {
+        match self {
+            Protocol::Http(http) => accept(incoming, http, service, handle),
+            Protocol::Https(https) => accept(incoming, https, service, handle),
+        }
+    }
+}
+
+/// Connection counting itself in ```unflushed``` from the first write until the next successful flush,
+/// so a shutdown can wait until the last responses are written out
+struct Tracked<S> {
+    inner: S,
+    dirty: bool,
+    unflushed: Arc<AtomicUsize>,
+}
+
+impl<S> Tracked<S> {
+    fn new(inner: S, unflushed: Arc<AtomicUsize>) -> Self {
+        Tracked { inner, dirty: false, unflushed }
+    }
+
+    fn set_dirty(&mut self, dirty: bool) {
+        if dirty != self.dirty {
+            self.dirty = dirty;
+            if dirty {
+                self.unflushed.fetch_add(1, Ordering::SeqCst);
+            } else {
+                self.unflushed.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
+    }
+}
+
+impl<S> Drop for Tracked<S> {
+    fn drop(&mut self) {
+        self.set_dirty(false);
+    }
+}
+
+impl<S: Read> Read for Tracked<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<S: Write> Write for Tracked<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // also dirty if the socket is not writable, the data is still buffered by the transport
+        if !buf.is_empty() {
+            self.set_dirty(true);
+        }
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()?;
+        self.set_dirty(false);
+        Ok(())
+    }
+}
+
+impl<S: AsyncRead> AsyncRead for Tracked<S> {}
+
+impl<S: AsyncWrite> AsyncWrite for Tracked<S> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.inner.shutdown()
+    }
+}
+
+impl<S: PeerAddr> PeerAddr for Tracked<S> {
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    fn peer_credentials(&self) -> Option<PeerCredentials> {
+        self.inner.peer_credentials()
+    }
+
+    fn peer_certificate(&self) -> Option<String> {
+        self.inner.peer_certificate()
+    }
+}
+
+/// a listener ready to be served
+pub struct Listener {
+    pub bound: Bound,
+    pub protocol: Protocol,
+    pub service: InternalServer,
+}
+
+fn accept<S, P>(incoming: S, proto: P, service: InternalServer, handle: Handle) -> Box<Future<Item=(), Error=io::Error>>
+    where S: Stream<Error=io::Error> + 'static,
+          S::Item: 'static,
+          P: BindServer<Pipeline, S::Item, ServiceRequest=DecodingResult, ServiceResponse=Response<Body>, ServiceError=io::Error> {
+    Box::new(incoming.for_each(move |socket| {
+        proto.bind_server(&handle, socket, service.clone());
+        Ok(())
+    }))
+}
+
+/// serves connections accepted on all listeners until the stopper is triggered.
+/// Afterwards in flight requests get ```grace_period``` to finish and their responses to be written, then all connections are closed.
+/// ```ready``` is notified once connections are accepted.
+pub fn serve(listeners: Vec<Listener>, stopper: ServerStopper, grace_period: Duration, ready: Option<Sender<()>>) -> io::Result<()> {
+    let mut core = Core::new()?;
+    let handle = core.handle();
+    let mut addrs = Vec::new();
+    let mut counters = Vec::new();
+    let mut accepts = Vec::new();
+    let mut result = Ok(());
+    let unflushed = Arc::new(AtomicUsize::new(0));
+    for listener in listeners.into_iter() {
+        let addr = listener.bound.local_addr()?;
+        counters.push(listener.service.in_flight.clone());
+        match listener.bound.accept(listener.protocol, listener.service, &handle, &unflushed) {
+            Ok(accept) => accepts.push(accept),
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        }
+        info!("Listening on {}", addr);
+        addrs.push(addr);
+    }
+    let in_flight = || counters.iter().map(|c| c.load(Ordering::SeqCst)).sum::<usize>();
+
+    if result.is_ok() && !accepts.is_empty() {
+        if let Some(ready) = ready {
+            let _ = ready.send(());
+        }
+        #[cfg(target_os = "linux")]
+        super::systemd::notify_state("READY=1");
+        let stop = stopper.wait().map_err(|_| io::Error::new(io::ErrorKind::Other, "Stop signal lost"));
+        let accept = select_all(accepts).map(|_| ()).map_err(|(e, _, _)| e);
+        result = core.run(accept.select(stop)).map(|_| ()).map_err(|(e, _)| e);
+    }
+    if let Err(ref e) = result {
+        error!("Stopped accepting connections: {}", e);
+    }
+
+    info!("Stopped accepting connections, {} requests in flight", in_flight());
+    #[cfg(target_os = "linux")]
+    super::systemd::notify_state("STOPPING=1");
+    let deadline = Instant::now() + grace_period;
+    while in_flight() > 0 {
+        let now = Instant::now();
+        if now >= deadline {
+            warn!("Shutdown grace period elapsed, dropping {} requests", in_flight());
+            break;
+        }
+        core.turn(Some(deadline - now));
+    }
+
+    // runs the connections notified about the last responses, afterwards waits until they are written
+    core.turn(Some(Duration::from_millis(0)));
+    while unflushed.load(Ordering::SeqCst) > 0 {
+        let now = Instant::now();
+        if now >= deadline {
+            warn!("Shutdown grace period elapsed, dropping {} unflushed connections", unflushed.load(Ordering::SeqCst));
+            break;
+        }
+        core.turn(Some(deadline - now));
+    }
+    #[cfg(unix)]
+    {
+        for addr in addrs.iter() {
+            if let ListenAddr::Unix(ref socket) = *addr {
+                socket.cleanup();
+            }
+        }
+    }
+    // dropping the core closes all remaining, idle connections
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracked_until_flushed() {
+        let unflushed = Arc::new(AtomicUsize::new(0));
+        let mut first = Tracked::new(Vec::new(), unflushed.clone());
+        let mut second = Tracked::new(Vec::new(), unflushed.clone());
+        first.write_all(b"HTTP/1.1 200 OK\r\n").unwrap();
+        first.write_all(b"\r\n").unwrap();
+        second.write_all(b"HTTP/1.1 200 OK\r\n").unwrap();
+        assert_eq!(2, unflushed.load(Ordering::SeqCst));
+
+        first.flush().unwrap();
+        assert_eq!(1, unflushed.load(Ordering::SeqCst));
+        drop(second);
+        assert_eq!(0, unflushed.load(Ordering::SeqCst));
+    }
+}
@@ -10,52 +10,157 @@ use std::io;
 use http::Response;
 use futures::future;
 use tokio_service::Service;
-use tokio_proto::TcpServer;
 use ::request::Request;
 use ::body::Body;
 use ::router::{Threading, Router, InternalRouter};
 use state::Container;
-use std::sync::atomic::{AtomicBool, Ordering};
-use native_tls::Pkcs12;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use futures::{Async, Poll};
+use futures::task::AtomicTask;
 use ::error::HttpError;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use futures_cpupool::{CpuPool, Builder as PoolBuilder};
 use mime_sniffer::MimeTypeSniffer;
+use ::metrics::{Metrics, UNMATCHED_ROUTE};
 
 mod codec;
+mod listener;
+mod handle;
+pub mod unix;
 pub mod tester;
-
-use self::codec::{Http, HttpCodecCfg, DecodingResult, DecodedRequest};
+pub mod proxy;
+pub mod accesslog;
+pub mod redirect;
+pub mod tls;
+pub mod tlsreload;
+pub mod config;
+#[cfg(target_os = "linux")]
+pub mod systemd;
+
+use self::codec::{Http, DecodingResult, DecodedRequest, RejectedRequest};
+use self::proxy::TrustedProxies;
+use self::accesslog::{AccessLog, AccessLogEntry};
+pub use self::codec::HttpCodecCfg;
+pub use self::handle::ServerHandle;
+pub use self::listener::ListenAddr;
+#[cfg(unix)]
+pub use self::unix::UnixSocket;
+pub use self::redirect::HttpsRedirect;
+pub use self::tls::{TlsConfig, Identity, ClientAuth};
+pub use self::tlsreload::{ReloadableTls, IntoTls};
+use self::listener::{Bound, Listener, Protocol};
+use self::tlsreload::TlsProto;
+use std::time::Instant;
 
 pub struct Server {
     pool: CpuPool,
-    addr: SocketAddr,
+    addr: ListenAddr,
     router: Arc<InternalRouter>,
     state: Arc<Container>,
     stopper: ServerStopper,
     codec_cfg: HttpCodecCfg,
+    trusted_proxies: Arc<TrustedProxies>,
+    access_log: Option<Arc<AccessLog>>,
+    grace_period: Duration,
+    listeners: Vec<(ListenAddr, ListenerMode)>,
+}
+
+/// how a listener serves its connections
+enum ListenerMode {
+    Http,
+    Https(ReloadableTls),
+    Redirect(Arc<HttpsRedirect>),
 }
 
+#[derive(Clone)]
 struct InternalServer {
     pool: CpuPool,
+    router: Arc<InternalRouter>,
     state: Arc<Container>,
+    trusted_proxies: Arc<TrustedProxies>,
+    access_log: Option<Arc<AccessLog>>,
+    metrics: Option<Arc<Metrics>>,
+    stopper: ServerStopper,
+    in_flight: Arc<AtomicUsize>,
+    redirect: Option<Arc<HttpsRedirect>>,
 }
 
+/// Stops a running server.
+/// The server stops accepting connections, waits for in flight requests up to the grace period
+/// (see ```Server::set_shutdown_grace_period```) and closes all connections afterwards.
+/// The stopper is available as state in every handler.
 #[derive(Debug, Clone)]
 pub struct ServerStopper {
-    stop: Arc<AtomicBool>,
+    inner: Arc<StopperInner>,
+}
+
+struct StopperInner {
+    stop: AtomicBool,
+    /// one registration per ```StopSignal```, removed when the signal is dropped
+    waiting: Mutex<Vec<Arc<AtomicTask>>>,
+}
+
+impl ::std::fmt::Debug for StopperInner {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.debug_struct("StopperInner").field("stop", &self.stop).finish()
+    }
 }
 
 impl ServerStopper {
+    /// triggers the shutdown, can be called from any thread
     pub fn stop(&self) {
-        self.stop.store(true, Ordering::SeqCst);
+        self.inner.stop.store(true, Ordering::SeqCst);
+        for task in self.inner.waiting.lock().unwrap().iter() {
+            task.notify();
+        }
+    }
+
+    /// returns true if the shutdown was triggered
+    pub fn is_stopped(&self) -> bool {
+        self.inner.stop.load(Ordering::SeqCst)
+    }
+
+    fn wait(&self) -> StopSignal {
+        let task = Arc::new(AtomicTask::new());
+        self.inner.waiting.lock().unwrap().push(task.clone());
+        StopSignal { stopper: self.clone(), task }
     }
 }
 
 impl Default for ServerStopper {
     fn default() -> Self {
-        ServerStopper { stop: Arc::new(AtomicBool::new(false)) }
+        ServerStopper { inner: Arc::new(StopperInner { stop: AtomicBool::new(false), waiting: Mutex::new(Vec::new()) }) }
+    }
+}
+
+/// resolves once the stopper was triggered
+struct StopSignal {
+    stopper: ServerStopper,
+    task: Arc<AtomicTask>,
+}
+
+impl Future for StopSignal {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        // registering first, stop might be called between the check and returning
+        self.task.register();
+        if self.stopper.is_stopped() {
+            Ok(Async::Ready(()))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+impl Drop for StopSignal {
+    fn drop(&mut self) {
+        let task = &self.task;
+        self.stopper.inner.waiting.lock().unwrap().retain(|waiting| !Arc::ptr_eq(waiting, task));
     }
 }
 
@@ -68,37 +173,79 @@ impl Service for InternalServer {
     type Future = Box<Future<Item=Response<Body>, Error=io::Error>>;
 
     fn call(&self, req: DecodingResult) -> Self::Future {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let in_flight = self.in_flight.clone();
+        let stopper = self.stopper.clone();
+        Box::new(self.handle(req).then(move |result| {
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+            result.map(|mut resp| {
+                if stopper.is_stopped() {
+                    resp.headers_mut().insert(::http::header::CONNECTION, ::http::header::HeaderValue::from_static("close"));
+                }
+                resp
+            })
+        }))
+    }
+}
+
+impl InternalServer {
+    fn handle(&self, req: DecodingResult) -> Box<Future<Item=Response<Body>, Error=io::Error>> {
+        if let Some(ref redirect) = self.redirect {
+            return Box::new(future::ok(redirect.handle(&req).into_inner()));
+        }
+        let start = Instant::now();
         let dec_req = match req {
-            DecodingResult::BodyTooLarge => return Box::new(future::ok(HttpError::bad_request("Request too large").into())),
-            DecodingResult::HeaderTooLarge => return Box::new(future::ok(HttpError::bad_request("Header too large").into())),
-            DecodingResult::RouteNotFound => return Box::new(future::ok(HttpError::not_found("Route not found").into())),
+            DecodingResult::BodyTooLarge(rej) => return self.reject(rej, HttpError::payload_too_large("Request too large"), start),
+            DecodingResult::HeaderTooLarge(rej) => return self.reject(rej, HttpError::header_too_large("Header too large"), start),
+            DecodingResult::UnsupportedMediaType(rej) => return self.reject(rej, HttpError::unsupported_media_type("Unsupported content type"), start),
+            DecodingResult::RouteNotFound(rej) => return self.reject(rej, HttpError::not_found("Route not found"), start),
             DecodingResult::Ok(res) => res
         };
 
-        let DecodedRequest { request: req, route, params } = dec_req;
-        debug!("Got request {:?}", req);
+        let DecodedRequest { request: req, route, params, remote_addr, peer_credentials, peer_certificate } = dec_req;
         let state = self.state.clone();
+        let trusted_proxies = self.trusted_proxies.clone();
+        let access_log = self.access_log.clone();
+        let metrics = self.metrics.clone();
         let local_route = route.clone();
+        let queued = match route.threading {
+            Threading::SEPERATE => self.metrics.clone(),
+            Threading::SAME => None,
+        };
+        if let Some(ref metrics) = queued {
+            metrics.queued();
+        }
 
         let r = move || {
+            if let Some(queued) = queued {
+                queued.dequeued();
+            }
+            if let Some(ref metrics) = metrics {
+                metrics.request_started();
+            }
             let mut request = Request::new(req, state, params);
-            let res = local_route.callback.handle(&mut request);
-            let res = if let Ok(res) = res {
-                Ok(enhance_content_type(res))
-            } else {
-                res
-            };
-
-            match res {
-                Ok(resp) => {
-                    trace!("Successfully handled request. Response: {:?}", &resp);
-                    future::ok(resp.into_inner())
-                }
-                Err(err) => {
-                    warn!("Failed to handle {:?}", &err);
-                    future::ok(::response::Response::from(err).into_inner())
-                }
+            debug!("Got request [{}] {:?}", request.id(), request);
+            if let Some(remote_addr) = remote_addr {
+                request.set_remote_addr(remote_addr);
             }
+            if let Some(peer_credentials) = peer_credentials {
+                request.set_peer_credentials(peer_credentials);
+            }
+            if let Some(peer_certificate) = peer_certificate {
+                request.set_peer_certificate(peer_certificate);
+            }
+            if let Some(forwarded) = trusted_proxies.resolve(&request) {
+                request.set_forwarded(forwarded);
+            }
+            let resp = enhance_content_type(local_route.process(&mut request));
+            trace!("Handled request [{}]. Response: {:?}", request.id(), &resp);
+            if let Some(metrics) = metrics {
+                metrics.request_finished(&local_route.path, request.method().as_str(), resp.status().as_u16(), start.elapsed());
+            }
+            if let Some(access_log) = access_log {
+                access_log.write(&AccessLogEntry::new(&request, &resp, start.elapsed()));
+            }
+            future::ok(resp.into_inner())
         };
 
         match route.threading {
@@ -106,6 +253,32 @@ impl Service for InternalServer {
             Threading::SEPERATE => Box::new(self.pool.spawn_fn(r)),
         }
     }
+
+    fn reject(&self, rejected: RejectedRequest, err: HttpError, start: Instant) -> Box<Future<Item=Response<Body>, Error=io::Error>> {
+        let mut request = ::http::Request::new(Body(None));
+        if let Some(ref method) = rejected.method {
+            *request.method_mut() = method.clone();
+        }
+        if let Some(ref uri) = rejected.uri {
+            *request.uri_mut() = uri.clone();
+        }
+        *request.headers_mut() = rejected.headers.clone();
+        let request = Request::new(request, self.state.clone(), ::request::Params::new());
+        debug!("Rejected request {:?} [{}]: {}", rejected, request.id(), err);
+        let mut response = self.router.render_error(&request, err);
+        if let Ok(id) = ::http::header::HeaderValue::from_str(request.id()) {
+            response.headers_mut().insert(::request::id::REQUEST_ID_HEADER, id);
+        }
+        if let Some(ref metrics) = self.metrics {
+            let method = rejected.method.as_ref().map(|m| m.as_str()).unwrap_or("-");
+            let route = rejected.route.as_ref().map(|r| r.as_str()).unwrap_or(UNMATCHED_ROUTE);
+            metrics.observe(route, method, response.status().as_u16(), start.elapsed());
+        }
+        if let Some(ref access_log) = self.access_log {
+            access_log.write(&AccessLogEntry::rejected(&rejected, &response, start.elapsed()));
+        }
+        Box::new(future::ok(response.into_inner()))
+    }
 }
 
 fn enhance_content_type(response: ::response::Response) -> ::response::Response {
@@ -138,25 +311,112 @@ fn enhance_content_type(response: ::response::Response) -> ::response::Response
 
 impl Server {
     pub fn new(addr: SocketAddr, r: Router) -> Self {
+        Server::listen(ListenAddr::Tcp(addr), r)
+    }
+
+    /// creates a server listening on a unix domain socket.
+    /// Requests received via unix sockets have no ```remote_addr```, but ```peer_credentials```
+    #[cfg(unix)]
+    pub fn new_unix(socket: UnixSocket, r: Router) -> Self {
+        Server::listen(ListenAddr::Unix(socket), r)
+    }
+
+    /// creates a server accepting connections on an already listening tcp or unix socket
+    #[cfg(unix)]
+    pub fn new_fd(fd: ::std::os::unix::io::RawFd, r: Router) -> Self {
+        Server::listen(ListenAddr::Fd(fd), r)
+    }
+
+    /// creates a server on the sockets passed by systemd socket activation.
+    /// The first socket is the main one, all others are served as additional http listeners.
+    /// Use ```systemd::listen_fds``` and ```systemd::named``` to assign the sockets by name yourself
+    #[cfg(target_os = "linux")]
+    pub fn from_systemd(r: Router) -> io::Result<Self> {
+        let mut fds = self::systemd::listen_fds()?.into_iter();
+        let first = fds.next().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No sockets passed by systemd"))?;
+        let mut server = Server::new_fd(first.fd, r);
+        for fd in fds {
+            server.add_http_listen_addr(ListenAddr::Fd(fd.fd));
+        }
+        Ok(server)
+    }
+
+    fn listen(addr: ListenAddr, r: Router) -> Self {
         let internal_router = InternalRouter::new(r);
         let pool = PoolBuilder::new().name_prefix("RIR_Worker").pool_size(20).create();
-        Server { codec_cfg: HttpCodecCfg::default(), stopper: ServerStopper::default(), addr: addr, router: Arc::new(internal_router), state: Arc::new(Container::new()), pool }
+        Server { codec_cfg: HttpCodecCfg::default(), stopper: ServerStopper::default(), addr, router: Arc::new(internal_router), state: Arc::new(Container::new()), pool, trusted_proxies: Arc::new(TrustedProxies::default()), access_log: None, grace_period: Duration::from_secs(30), listeners: Vec::new() }
+    }
+
+    /// sets how long in flight requests may take to finish after the server was stopped.
+    /// The default is 30 seconds
+    pub fn set_shutdown_grace_period(&mut self, grace_period: Duration) {
+        self.grace_period = grace_period;
+    }
+
+    /// enables access logging, one line per request including requests rejected by the http parser
+    pub fn set_access_log(&mut self, access_log: AccessLog) {
+        self.access_log = Some(Arc::new(access_log));
     }
 
+    /// sets the proxies whose forwarding headers are used to resolve the client ip, scheme and host
+    /// see ```Request::client_ip```
+    pub fn set_trusted_proxies(&mut self, proxies: TrustedProxies) {
+        self.trusted_proxies = Arc::new(proxies);
+    }
+
+    /// sets the limits of the http parser, see ```HttpCodecCfg```
     pub fn set_codec_cfg(&mut self, cfg: HttpCodecCfg) {
         self.codec_cfg = cfg;
     }
 
+    /// additionally serves http on the given address, sharing router, state and thread pool
+    pub fn add_http_listener(&mut self, addr: SocketAddr) {
+        self.add_http_listen_addr(ListenAddr::Tcp(addr));
+    }
+
+    /// like ```add_http_listener```, for unix sockets and inherited file descriptors
+    pub fn add_http_listen_addr(&mut self, addr: ListenAddr) {
+        self.listeners.push((addr, ListenerMode::Http));
+    }
+
+    /// additionally serves https on the given address, sharing router, state and thread pool.
+    /// Accepts a ```TlsConfig```, ```Identity``` or ```ReloadableTls```, with ```tls-native``` also a ```native_tls::Pkcs12```
+    pub fn add_https_listener<C: IntoTls>(&mut self, addr: SocketAddr, tls: C) -> io::Result<()> {
+        self.add_https_listen_addr(ListenAddr::Tcp(addr), tls)
+    }
+
+    /// like ```add_https_listener```, for unix sockets and inherited file descriptors
+    pub fn add_https_listen_addr<C: IntoTls>(&mut self, addr: ListenAddr, tls: C) -> io::Result<()> {
+        let tls = tls.into_tls()?;
+        self.listeners.push((addr, ListenerMode::Https(tls)));
+        Ok(())
+    }
+
+    /// additionally listens on the given address and redirects every request to https
+    pub fn add_redirect_listener(&mut self, addr: SocketAddr, redirect: HttpsRedirect) {
+        self.add_redirect_listen_addr(ListenAddr::Tcp(addr), redirect);
+    }
+
+    /// like ```add_redirect_listener```, for unix sockets and inherited file descriptors
+    pub fn add_redirect_listen_addr(&mut self, addr: ListenAddr, redirect: HttpsRedirect) {
+        self.listeners.push((addr, ListenerMode::Redirect(Arc::new(redirect))));
+    }
+
     pub fn set_thread_pool_size(&mut self, size: usize) {
         self.pool = PoolBuilder::new().name_prefix("RIR_Worker").pool_size(size).create();
     }
 
-    pub fn start_http_non_blocking(self) -> Result<ServerStopper, ()> {
-        use std::thread::spawn;
-        let stopper = self.stopper.clone();
+    /// binds the socket and serves http in a new thread.
+    /// Bind to port 0 and use ```ServerHandle::local_addr``` to get a free port.
+    pub fn start_http_non_blocking(self) -> io::Result<ServerHandle> {
+        self.spawn(ListenerMode::Http)
+    }
 
-        spawn(|| self.start_http());
-        Ok(stopper)
+    /// binds the socket and serves https in a new thread.
+    /// Accepts a ```TlsConfig```, ```Identity``` or ```ReloadableTls```, with ```tls-native``` also a ```native_tls::Pkcs12```
+    pub fn start_https_non_blocking<C: IntoTls>(self, tls: C) -> io::Result<ServerHandle> {
+        let tls = tls.into_tls()?;
+        self.spawn(ListenerMode::Https(tls))
     }
 
     pub fn start_testing(self) -> self::tester::ServerTester {
@@ -165,39 +425,72 @@ impl Server {
         ServerTester::new(self.router, self.state)
     }
 
-    pub fn start_http(self) {
-        //fixme currently shutdown not supported by tcpserver, next version  -> Result<ServerStopper, ()> {
-        let addr = self.addr.clone();
-        let state = self.state;
-        state.set(self.stopper);
-        let http = Http { router: self.router.clone(), config: self.codec_cfg };
-        let pool = self.pool;
-        TcpServer::new(http, addr).serve(move || Ok(InternalServer { state: state.clone(), pool: pool.clone() }));
-
-        //        let stopper = ServerStopper { stop: Arc::new(::std::sync::atomic::AtomicBool::new(false)) };
-        //        Ok(stopper)
+    /// serves http until the server is stopped via the ```ServerStopper```, returns an error if a socket can not be bound
+    pub fn start_http(self) -> io::Result<()> {
+        self.serve(ListenerMode::Http)
     }
 
-    pub fn start_https(self, pkcs: Pkcs12) {
-        //fixme currently shutdown not supported by tcpserver, next version  -> Result<ServerStopper, ()> {
-        use native_tls::TlsAcceptor;
-        use tokio_proto::TcpServer;
-        use tokio_tls::proto;
+    /// serves https until the server is stopped via the ```ServerStopper```.
+    /// Accepts a ```TlsConfig```, ```Identity``` or ```ReloadableTls```, with ```tls-native``` also a ```native_tls::Pkcs12```, returns an error if the certificates can not be loaded
+    pub fn start_https<C: IntoTls>(self, tls: C) -> io::Result<()> {
+        let tls = tls.into_tls()?;
+        self.serve(ListenerMode::Https(tls))
+    }
 
-        let tls_cx = TlsAcceptor::builder(pkcs).unwrap()
-            .build().unwrap();
+    fn serve(mut self, mode: ListenerMode) -> io::Result<()> {
+        let bound = self.bind(mode)?;
+        self.run(bound, None)
+    }
 
-        let router = self.router;
-        let http = Http { router: router.clone(), config: self.codec_cfg };
-        let proto = proto::Server::new(http, tls_cx);
+    fn spawn(mut self, mode: ListenerMode) -> io::Result<ServerHandle> {
+        let bound = self.bind(mode)?;
+        let local_addrs = bound.iter().map(|&(ref bound, _)| bound.local_addr()).collect::<io::Result<Vec<_>>>()?;
+        let stopper = self.stopper.clone();
+        let (ready, ready_receiver) = ::std::sync::mpsc::channel();
+        let thread = ::std::thread::Builder::new()
+            .name(format!("RIR_Server_{}", local_addrs[0]))
+            .spawn(move || self.run(bound, Some(ready)))?;
+        Ok(ServerHandle::new(local_addrs, stopper, ready_receiver, thread))
+    }
 
-        let addr = self.addr.clone();
-        let srv = TcpServer::new(proto, addr);
-        let state = self.state;
-        let pool = self.pool;
-        srv.serve(move || Ok(InternalServer { state: state.clone(), pool: pool.clone() }));
+    /// binds the main address and all additional listeners
+    fn bind(&mut self, mode: ListenerMode) -> io::Result<Vec<(Bound, ListenerMode)>> {
+        let mut bound = vec![(self.addr.bind()?, mode)];
+        for (addr, mode) in self.listeners.drain(..) {
+            bound.push((addr.bind()?, mode));
+        }
+        Ok(bound)
+    }
 
-        //        Ok(ServerStopper::default())
+    fn run(self, bound: Vec<(Bound, ListenerMode)>, ready: Option<::std::sync::mpsc::Sender<()>>) -> io::Result<()> {
+        self.state.set(self.stopper.clone());
+        let internal = InternalServer {
+            metrics: self.router.metrics().cloned(),
+            router: self.router.clone(),
+            state: self.state,
+            pool: self.pool,
+            trusted_proxies: self.trusted_proxies,
+            access_log: self.access_log,
+            stopper: self.stopper.clone(),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            redirect: None,
+        };
+        let mut listeners = Vec::new();
+        for (bound, mode) in bound {
+            let http = Http { router: self.router.clone(), config: self.codec_cfg };
+            let mut service = internal.clone();
+            service.in_flight = Arc::new(AtomicUsize::new(0));
+            let protocol = match mode {
+                ListenerMode::Http => Protocol::Http(http),
+                ListenerMode::Https(tls) => Protocol::Https(TlsProto::new(http, tls)),
+                ListenerMode::Redirect(redirect) => {
+                    service.redirect = Some(redirect);
+                    Protocol::Http(http)
+                }
+            };
+            listeners.push(Listener { bound, protocol, service });
+        }
+        listener::serve(listeners, self.stopper, self.grace_period, ready)
     }
 
     pub fn add_state<T: Send + Sync + 'static>(&self, state: T) {
@@ -219,9 +512,13 @@ impl Default for Server {
             pool: PoolBuilder::new().name_prefix("RIR_Worker").pool_size(20).create(),
             codec_cfg: HttpCodecCfg::default(),
             stopper: ServerStopper::default(),
-            addr: "127.0.0.1:8080".parse().unwrap(),
+            addr: ListenAddr::Tcp("127.0.0.1:8080".parse().unwrap()),
             router: Arc::new(InternalRouter::new(Router::new())),
-            state: Arc::new(Container::new())
+            state: Arc::new(Container::new()),
+            trusted_proxies: Arc::new(TrustedProxies::default()),
+            access_log: None,
+            grace_period: Duration::from_secs(30),
+            listeners: Vec::new(),
         }
     }
 }
@@ -231,6 +528,39 @@ impl Default for Server {
 mod tests {
     use super::*;
 
+    #[test]
+    fn stopper_wakes_waiting_server() {
+        let stopper = ServerStopper::default();
+        let remote = stopper.clone();
+        let thread = ::std::thread::spawn(move || {
+            ::std::thread::sleep(Duration::from_millis(20));
+            remote.stop();
+        });
+        assert_eq!(Ok(()), stopper.wait().wait());
+        assert!(stopper.is_stopped());
+        thread.join().unwrap();
+        assert_eq!(0, stopper.inner.waiting.lock().unwrap().len());
+    }
+
+    #[test]
+    fn stop_signal_registers_once() {
+        let stopper = ServerStopper::default();
+        let mut signal = ::futures::executor::spawn(stopper.wait());
+        let notify = ::futures::executor::NotifyHandle::from(Arc::new(NoopNotify));
+        for _ in 0..10 {
+            assert_eq!(Ok(Async::NotReady), signal.poll_future_notify(&notify, 0));
+        }
+        assert_eq!(1, stopper.inner.waiting.lock().unwrap().len());
+        stopper.stop();
+        assert_eq!(Ok(Async::Ready(())), signal.poll_future_notify(&notify, 0));
+    }
+
+    struct NoopNotify;
+
+    impl ::futures::executor::Notify for NoopNotify {
+        fn notify(&self, _: usize) {}
+    }
+
     #[test]
     fn test_guess_content_type() {
         test_content_type(None, b"<body");
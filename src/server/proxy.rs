@@ -0,0 +1,221 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Resolution of the real client address behind trusted reverse proxies.
+//! Only if the peer of the connection is a trusted proxy the ```Forwarded```,
+//! ```X-Forwarded-For```, ```X-Forwarded-Proto``` and ```X-Forwarded-Host``` headers are used.
+
+use std::io;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use request::Request;
+
+/// Client information forwarded by a trusted proxy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForwardedInfo {
+    /// ip of the client that sent the request to the first proxy
+    pub client_ip: IpAddr,
+    /// scheme the client used, eg. https
+    pub scheme: Option<String>,
+    /// host the client requested
+    pub host: Option<String>,
+}
+
+/// List of networks whose forwarding headers are trusted
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    networks: Vec<Network>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Network {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Network {
+    fn parse(value: &str) -> io::Result<Self> {
+        let mut split = value.trim().splitn(2, '/');
+        let addr = split.next().unwrap_or("");
+        let addr = IpAddr::from_str(addr).map_err(|_| invalid_input(format!("Invalid proxy address {}", value)))?;
+        let max_prefix = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix = match split.next() {
+            Some(prefix) => u8::from_str(prefix).ok().filter(|p| *p <= max_prefix)
+                .ok_or_else(|| invalid_input(format!("Invalid proxy network {}", value)))?,
+            None => max_prefix,
+        };
+        Ok(Network { addr, prefix })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        let (network, ip): (Vec<u8>, Vec<u8>) = match (self.addr, ip) {
+            (IpAddr::V4(n), IpAddr::V4(i)) => (n.octets().to_vec(), i.octets().to_vec()),
+            (IpAddr::V6(n), IpAddr::V6(i)) => (n.octets().to_vec(), i.octets().to_vec()),
+            (IpAddr::V6(n), IpAddr::V4(i)) => (n.octets().to_vec(), i.to_ipv6_mapped().octets().to_vec()),
+            (IpAddr::V4(n), IpAddr::V6(i)) => match i.to_ipv4() {
+                Some(i) => (n.octets().to_vec(), i.octets().to_vec()),
+                None => return false,
+            },
+        };
+        let mut remaining = self.prefix as usize;
+        for (n, i) in network.iter().zip(ip.iter()) {
+            if remaining == 0 {
+                break;
+            }
+            let bits = ::std::cmp::min(remaining, 8);
+            let mask = (0xFFu16 << (8 - bits)) as u8;
+            if n & mask != i & mask {
+                return false;
+            }
+            remaining -= bits;
+        }
+        true
+    }
+}
+
+fn invalid_input(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+impl TrustedProxies {
+    /// creates an empty list, no proxy is trusted
+    pub fn new() -> Self {
+        TrustedProxies::default()
+    }
+
+    /// trusts the given address or network in CIDR notation, eg. ```10.0.0.0/8``` or ```::1```.
+    /// Returns an ```InvalidInput``` error if the network can not be parsed
+    pub fn trust<S: AsRef<str>>(mut self, network: S) -> io::Result<Self> {
+        self.networks.push(Network::parse(network.as_ref())?);
+        Ok(self)
+    }
+
+    /// returns true if the given ip is a trusted proxy
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.networks.iter().any(|n| n.contains(ip))
+    }
+
+    /// resolves the forwarded client information if the peer of the request is a trusted proxy
+    pub fn resolve(&self, req: &Request) -> Option<ForwardedInfo> {
+        let peer = req.remote_addr()?.ip();
+        if !self.is_trusted(peer) {
+            return None;
+        }
+
+        let (hops, scheme, host) = match req.header(&::http::header::FORWARDED) {
+            Some(forwarded) => parse_forwarded(forwarded),
+            None => {
+                let hops = req.header_str("x-forwarded-for")
+                    .map(|v| v.split(',').filter_map(|ip| parse_ip(ip)).collect())
+                    .unwrap_or_else(Vec::new);
+                let first = |name: &str| req.header_str(name).and_then(|v| v.split(',').next()).map(|v| v.trim().to_string());
+                (hops, first("x-forwarded-proto"), first("x-forwarded-host"))
+            }
+        };
+
+        // walk from the nearest proxy backwards, the first untrusted address is the client
+        let client_ip = hops.iter().rev().find(|ip| !self.is_trusted(**ip)).or(hops.first()).cloned();
+        if client_ip.is_none() && scheme.is_none() && host.is_none() {
+            return None;
+        }
+        Some(ForwardedInfo { client_ip: client_ip.unwrap_or(peer), scheme, host })
+    }
+}
+
+/// parses a RFC 7239 header, returns all ```for``` addresses and the first ```proto``` and ```host```
+fn parse_forwarded(value: &str) -> (Vec<IpAddr>, Option<String>, Option<String>) {
+    let mut hops = Vec::new();
+    let mut scheme = None;
+    let mut host = None;
+    for element in value.split(',') {
+        for pair in element.split(';') {
+            let mut split = pair.splitn(2, '=');
+            let key = split.next().unwrap_or("").trim().to_lowercase();
+            let value = split.next().unwrap_or("").trim().trim_matches('"');
+            match key.as_str() {
+                "for" => if let Some(ip) = parse_ip(value) {
+                    hops.push(ip);
+                },
+                "proto" if scheme.is_none() => scheme = Some(value.to_lowercase()),
+                "host" if host.is_none() => host = Some(value.to_string()),
+                _ => {}
+            }
+        }
+    }
+    (hops, scheme, host)
+}
+
+/// parses ```1.2.3.4```, ```1.2.3.4:80```, ```[::1]``` and ```[::1]:80```
+fn parse_ip(value: &str) -> Option<IpAddr> {
+    let value = value.trim();
+    if let Ok(ip) = IpAddr::from_str(value) {
+        return Some(ip);
+    }
+    if value.starts_with('[') {
+        let end = value.find(']')?;
+        return IpAddr::from_str(&value[1..end]).ok();
+    }
+    let index = value.rfind(':')?;
+    IpAddr::from_str(&value[..index]).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(peer: &str, headers: &[(&'static str, &'static str)]) -> Request {
+        let mut builder = ::http::request::Builder::new();
+        builder.uri("/");
+        for &(name, value) in headers.iter() {
+            builder.header(name, value);
+        }
+        let mut req = Request::from_http(builder.body(::body::Body::empty()).unwrap());
+        req.set_remote_addr(peer.parse().unwrap());
+        req
+    }
+
+    #[test]
+    fn networks() {
+        let proxies = TrustedProxies::new().trust("10.0.0.0/8").unwrap().trust("::1").unwrap();
+        assert!(proxies.is_trusted("10.1.2.3".parse().unwrap()));
+        assert!(!proxies.is_trusted("11.1.2.3".parse().unwrap()));
+        assert!(proxies.is_trusted("::1".parse().unwrap()));
+        assert_eq!(io::ErrorKind::InvalidInput, TrustedProxies::new().trust("10.0.0.0/33").unwrap_err().kind());
+        assert!(TrustedProxies::new().trust("proxy.local").is_err());
+    }
+
+    #[test]
+    fn x_forwarded_for() {
+        let proxies = TrustedProxies::new().trust("10.0.0.0/8").unwrap();
+        let req = request("10.0.0.1:4000", &[("x-forwarded-for", "1.1.1.1, 2.2.2.2, 10.0.0.5"), ("x-forwarded-proto", "https")]);
+        let info = proxies.resolve(&req).unwrap();
+        assert_eq!("2.2.2.2".parse::<IpAddr>().unwrap(), info.client_ip);
+        assert_eq!(Some("https".to_string()), info.scheme);
+    }
+
+    #[test]
+    fn forwarded_header() {
+        let proxies = TrustedProxies::new().trust("127.0.0.1").unwrap();
+        let req = request("127.0.0.1:4000", &[("forwarded", "for=\"[2001:db8::1]:4711\";proto=https;host=example.com")]);
+        let info = proxies.resolve(&req).unwrap();
+        assert_eq!("2001:db8::1".parse::<IpAddr>().unwrap(), info.client_ip);
+        assert_eq!(Some("example.com".to_string()), info.host);
+    }
+
+    #[test]
+    fn untrusted_peer() {
+        let proxies = TrustedProxies::new().trust("10.0.0.0/8").unwrap();
+        let req = request("1.2.3.4:4000", &[("x-forwarded-for", "6.6.6.6")]);
+        assert!(proxies.resolve(&req).is_none());
+    }
+}
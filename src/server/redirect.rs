@@ -0,0 +1,120 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Redirect only listener sending every request to the https origin.
+//!
+//! ```rust,no_run
+//! # extern crate rest_in_rust;
+//! # use rest_in_rust::*;
+//! # use rest_in_rust::server::{HttpsRedirect, Identity};
+//! # fn main() {
+//! # let cert = Identity::from_pem_files("cert.pem", "key.pem").unwrap();
+//! let mut s = Server::new("0.0.0.0:443".parse().unwrap(), Router::new());
+//! s.add_redirect_listener("0.0.0.0:80".parse().unwrap(), HttpsRedirect::new(443));
+//! s.start_https(cert).unwrap();
+//! # }
+//! ```
+
+use http::{StatusCode, Uri};
+use http::header::{HeaderMap, HeaderValue, LOCATION, HOST};
+
+use error::HttpError;
+use response::Response;
+use super::codec::DecodingResult;
+
+/// Configuration of a redirect only listener
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpsRedirect {
+    port: u16,
+    host: Option<String>,
+    status: StatusCode,
+}
+
+impl HttpsRedirect {
+    /// redirects to the host of the request on the given https port with 308 Permanent Redirect
+    pub fn new(https_port: u16) -> Self {
+        HttpsRedirect { port: https_port, host: None, status: StatusCode::PERMANENT_REDIRECT }
+    }
+
+    /// redirects to the given host instead of the host the client requested
+    pub fn host<S: Into<String>>(mut self, host: S) -> Self {
+        self.host = Some(host.into());
+        self
+    }
+
+    /// answers with 301 Moved Permanently instead of 308, clients might change the method to GET
+    pub fn moved_permanently(mut self) -> Self {
+        self.status = StatusCode::MOVED_PERMANENTLY;
+        self
+    }
+
+    /// returns the redirect response for the decoded request
+    pub(crate) fn handle(&self, req: &DecodingResult) -> Response {
+        let location = match *req {
+            DecodingResult::Ok(ref dec) => self.location(dec.request.headers(), dec.request.uri()),
+            DecodingResult::RouteNotFound(ref rej) => rej.uri.as_ref().and_then(|uri| self.location(&rej.headers, uri)),
+            DecodingResult::HeaderTooLarge(_) | DecodingResult::BodyTooLarge(_) | DecodingResult::UnsupportedMediaType(_) => None,
+        };
+        let location = location.and_then(|l| HeaderValue::from_str(&l).ok());
+        match location {
+            Some(location) => {
+                debug!("Redirecting to {:?}", location);
+                Response::builder().status(self.status).header(LOCATION, location).build()
+                    .unwrap_or_else(Response::from)
+            }
+            None => Response::from(HttpError::bad_request("Can not redirect request without host")),
+        }
+    }
+
+    fn location(&self, headers: &HeaderMap, uri: &Uri) -> Option<String> {
+        let host = match self.host {
+            Some(ref host) => host.as_str(),
+            None => {
+                let host = headers.get(HOST).and_then(|h| h.to_str().ok()).or_else(|| uri.host())?;
+                strip_port(host)
+            }
+        };
+        let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+        if self.port == 443 {
+            Some(format!("https://{}{}", host, path))
+        } else {
+            Some(format!("https://{}:{}{}", host, self.port, path))
+        }
+    }
+}
+
+/// removes the port of ```example.com:80``` or ```[::1]:80```
+fn strip_port(host: &str) -> &str {
+    match host.rfind(':') {
+        Some(index) if !host[index..].contains(']') && host[index + 1..].bytes().all(|b| b.is_ascii_digit()) => &host[..index],
+        _ => host,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn location(redirect: &HttpsRedirect, host: &'static str, uri: &str) -> Option<String> {
+        let mut headers = HeaderMap::new();
+        headers.insert(HOST, HeaderValue::from_static(host));
+        redirect.location(&headers, &Uri::from_str(uri).unwrap())
+    }
+
+    #[test]
+    fn locations() {
+        let redirect = HttpsRedirect::new(443);
+        assert_eq!(Some("https://example.com/a?b=c".to_string()), location(&redirect, "example.com:80", "/a?b=c"));
+        assert_eq!(Some("https://[::1]/".to_string()), location(&redirect, "[::1]:8080", "/"));
+
+        let redirect = HttpsRedirect::new(8443).host("secure.example.com");
+        assert_eq!(Some("https://secure.example.com:8443/x".to_string()), location(&redirect, "example.com", "/x"));
+    }
+}
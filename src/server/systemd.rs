@@ -0,0 +1,173 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Systemd socket activation and readiness notification.
+//!
+//! Sockets passed via ```LISTEN_FDS``` are adopted instead of binding a new socket,
+//! so the service can be restarted without refusing connections.
+//! If ```NOTIFY_SOCKET``` is set the server sends ```READY=1``` once it accepts connections
+//! and ```STOPPING=1``` when it shuts down.
+//!
+//! ```rust,no_run
+//! # use rest_in_rust::*;
+//! let s = Server::from_systemd(Router::new()).unwrap();
+//! s.start_http().unwrap();
+//! ```
+//!
+//! Sockets named via ```FileDescriptorName=``` can be assigned to different listeners:
+//!
+//! ```rust,no_run
+//! # use rest_in_rust::*;
+//! use rest_in_rust::server::{systemd, ListenAddr, HttpsRedirect};
+//!
+//! let fds = systemd::listen_fds().unwrap();
+//! let https = systemd::named(&fds, "https");
+//! let mut s = Server::new_fd(https[0].fd, Router::new());
+//! for redirect in systemd::named(&fds, "http") {
+//!     s.add_redirect_listen_addr(ListenAddr::Fd(redirect.fd), HttpsRedirect::new(443));
+//! }
+//! ```
+
+use std::env;
+use std::io;
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixDatagram;
+use std::process;
+use libc;
+
+/// first file descriptor passed by systemd
+const LISTEN_FDS_START: RawFd = 3;
+
+/// A socket passed by systemd
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListenFd {
+    pub fd: RawFd,
+    /// name set via ```FileDescriptorName=``` in the socket unit
+    pub name: Option<String>,
+}
+
+/// returns the sockets passed via socket activation, empty if the process was not socket activated.
+/// The environment variables are removed, so the sockets can only be taken once
+pub fn listen_fds() -> io::Result<Vec<ListenFd>> {
+    let fds = activated(|var| env::var(var).ok(), process::id());
+    if fds.is_empty() {
+        return Ok(fds);
+    }
+    for var in &["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        env::remove_var(var);
+    }
+
+    for fd in fds.iter() {
+        // the sockets must not leak into child processes
+        if unsafe { libc::fcntl(fd.fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    debug!("Got {} sockets from systemd: {:?}", fds.len(), fds);
+    Ok(fds)
+}
+
+/// the sockets named ```name``` via ```FileDescriptorName=```, several sockets can share a name
+pub fn named(fds: &[ListenFd], name: &str) -> Vec<ListenFd> {
+    fds.iter().filter(|fd| fd.name.as_ref().map(|n| n == name).unwrap_or(false)).cloned().collect()
+}
+
+/// the sockets described by the activation variables read via ```var```, if they are meant for ```pid```
+fn activated<F: Fn(&str) -> Option<String>>(var: F, pid: u32) -> Vec<ListenFd> {
+    if var("LISTEN_PID").and_then(|p| p.parse::<u32>().ok()) != Some(pid) {
+        return Vec::new();
+    }
+    let count = var("LISTEN_FDS").and_then(|count| count.parse::<RawFd>().ok()).unwrap_or(0);
+    let names: Vec<String> = var("LISTEN_FDNAMES").map(|names| names.split(':').map(String::from).collect()).unwrap_or_default();
+    (0..count).map(|i| ListenFd { fd: LISTEN_FDS_START + i, name: names.get(i as usize).cloned() }).collect()
+}
+
+/// sends a state like ```READY=1``` to the systemd notify socket.
+/// Returns false if ```NOTIFY_SOCKET``` is not set
+pub fn notify(state: &str) -> io::Result<bool> {
+    match env::var("NOTIFY_SOCKET") {
+        Ok(path) => notify_socket(&path, state).map(|_| true),
+        Err(_) => Ok(false),
+    }
+}
+
+fn notify_socket(path: &str, state: &str) -> io::Result<()> {
+    let mut addr: libc::sockaddr_un = unsafe { mem::zeroed() };
+    addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+    let bytes = path.as_bytes();
+    if bytes.is_empty() || bytes.len() >= addr.sun_path.len() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid NOTIFY_SOCKET"));
+    }
+    for (i, b) in bytes.iter().enumerate() {
+        addr.sun_path[i] = *b as libc::c_char;
+    }
+    // a leading @ denotes an abstract socket
+    if bytes[0] == b'@' {
+        addr.sun_path[0] = 0;
+    }
+    let len = mem::size_of::<libc::sa_family_t>() + bytes.len();
+
+    let socket = UnixDatagram::unbound()?;
+    let sent = unsafe {
+        libc::sendto(socket.as_raw_fd(), state.as_ptr() as *const libc::c_void, state.len(), libc::MSG_NOSIGNAL,
+                     &addr as *const libc::sockaddr_un as *const libc::sockaddr, len as libc::socklen_t)
+    };
+    if sent < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// notifies systemd, failures are only logged
+pub(crate) fn notify_state(state: &str) {
+    match notify(state) {
+        Ok(true) => debug!("Notified systemd: {}", state),
+        Ok(false) => {}
+        Err(e) => warn!("Could not notify systemd about {}: {}", state, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn notify_socket() {
+        let dir = TempDir::new("systemd").unwrap();
+        let path = dir.path().join("notify");
+        let receiver = UnixDatagram::bind(&path).unwrap();
+
+        notify_socket(path.to_str().unwrap(), "READY=1").unwrap();
+        assert!(notify_socket("", "READY=1").is_err());
+
+        let mut buf = [0u8; 64];
+        let read = receiver.recv(&mut buf).unwrap();
+        assert_eq!(b"READY=1", &buf[..read]);
+    }
+
+    #[test]
+    fn activation_variables() {
+        let vars = |pid: &'static str| move |var: &str| match var {
+            "LISTEN_PID" => Some(pid.to_string()),
+            "LISTEN_FDS" => Some("3".to_string()),
+            "LISTEN_FDNAMES" => Some("https:http:http".to_string()),
+            _ => None,
+        };
+        assert!(activated(vars("1"), 42).is_empty());
+        assert!(activated(|_| None, 42).is_empty());
+
+        let fds = activated(vars("42"), 42);
+        assert_eq!(vec![3, 4, 5], fds.iter().map(|fd| fd.fd).collect::<Vec<_>>());
+        assert_eq!(vec![3], named(&fds, "https").iter().map(|fd| fd.fd).collect::<Vec<_>>());
+        assert_eq!(vec![4, 5], named(&fds, "http").iter().map(|fd| fd.fd).collect::<Vec<_>>());
+        assert!(named(&fds, "admin").is_empty());
+    }
+}
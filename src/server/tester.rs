@@ -31,15 +31,15 @@ impl ServerTester {
         let o = self.router.resolve(req.method(), req.uri().path());
         let (route, param) = match o {
             Some(val) => val,
-            None => return HttpError::not_found(req.uri().path()).into(),
+            None => {
+                let path = req.uri().path().to_string();
+                let r = RestRequest::new(req, self.state.clone(), ::request::Params::new());
+                return self.router.render_error(&r, HttpError::not_found(path)).into_inner();
+            }
         };
 
         let mut r = RestRequest::new(req, self.state.clone(), param.into());
 
-        let response: ::response::Response = match route.callback.handle(&mut r) {
-            Ok(response) => response,
-            Err(err) => err.into(),
-        };
-        response.into_inner()
+        route.process(&mut r).into_inner()
     }
 }
\ No newline at end of file
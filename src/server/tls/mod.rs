@@ -0,0 +1,170 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Tls configuration with pem certificates, sni and client certificates.
+//!
+//! The configuration is independent of the tls backend, which is selected via cargo features:
+//! ```tls-native``` (default) uses ```native-tls```, ```tls-rustls``` uses the pure rust ```rustls```.
+//!
+//! With ```tls-native``` PEM certificates, SNI and client certificates are only supported with the openssl backend,
+//! on windows and macos only a plain ```Pkcs12``` identity can be used.
+//! With ```tls-rustls``` only PEM certificates with rsa keys are supported.
+//!
+//! ```rust,no_run
+//! # use rest_in_rust::*;
+//! use rest_in_rust::server::{TlsConfig, Identity, ClientAuth};
+//!
+//! let tls = TlsConfig::new(Identity::from_pem_files("cert.pem", "key.pem").unwrap())
+//!     .sni("api.example.com", Identity::from_pem_files("api.pem", "api-key.pem").unwrap())
+//!     .client_auth(ClientAuth::Required("clients-ca.pem".into()));
+//!
+//! let s = Server::new("0.0.0.0:443".parse().unwrap(), Router::new());
+//! s.start_https(tls).unwrap();
+//! ```
+
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[cfg(all(feature = "tls-native", feature = "tls-rustls"))]
+compile_error!("The features tls-native and tls-rustls can not be enabled at the same time");
+#[cfg(not(any(feature = "tls-native", feature = "tls-rustls")))]
+compile_error!("Either the feature tls-native or tls-rustls has to be enabled");
+
+#[cfg(feature = "tls-native")]
+mod native;
+#[cfg(feature = "tls-native")]
+use self::native as imp;
+#[cfg(feature = "tls-rustls")]
+mod rustls;
+#[cfg(feature = "tls-rustls")]
+use self::rustls as imp;
+
+pub(crate) use self::imp::{Acceptor, Handshake, Stream, accept};
+
+/// Certificate chain and private key of the server
+pub struct Identity {
+    inner: imp::Identity,
+}
+
+impl Identity {
+    /// parses a pem encoded certificate chain, starting with the server certificate, and a pem encoded private key
+    pub fn from_pem(cert_chain: &[u8], key: &[u8]) -> io::Result<Self> {
+        Ok(Identity { inner: imp::from_pem(cert_chain, key)? })
+    }
+
+    /// reads the pem encoded certificate chain and private key from files
+    pub fn from_pem_files<C: AsRef<Path>, K: AsRef<Path>>(cert_chain: C, key: K) -> io::Result<Self> {
+        Identity::from_pem(&fs::read(cert_chain)?, &fs::read(key)?)
+    }
+}
+
+/// Verification of client certificates
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientAuth {
+    /// client certificates are not requested
+    None,
+    /// client certificates are requested and verified against the pem encoded ca bundle if the client sends one
+    Optional(PathBuf),
+    /// connections without a client certificate signed by the pem encoded ca bundle are refused
+    Required(PathBuf),
+}
+
+impl Default for ClientAuth {
+    fn default() -> Self {
+        ClientAuth::None
+    }
+}
+
+/// Tls configuration of a https listener
+pub struct TlsConfig {
+    identity: Identity,
+    sni: Vec<(String, Identity)>,
+    client_auth: ClientAuth,
+}
+
+impl TlsConfig {
+    /// uses the identity for all connections which don't match a sni name
+    pub fn new<I: Into<Identity>>(identity: I) -> Self {
+        TlsConfig { identity: identity.into(), sni: Vec::new(), client_auth: ClientAuth::default() }
+    }
+
+    /// uses the identity for connections requesting the given server name.
+    /// Wildcards like ```*.example.com``` match exactly one label
+    pub fn sni<S: Into<String>, I: Into<Identity>>(mut self, server_name: S, identity: I) -> Self {
+        self.sni.push((server_name.into().to_lowercase(), identity.into()));
+        self
+    }
+
+    /// sets the verification of client certificates, the default is ```ClientAuth::None```
+    pub fn client_auth(mut self, client_auth: ClientAuth) -> Self {
+        self.client_auth = client_auth;
+        self
+    }
+
+    /// builds the acceptor, errors when certificates can not be loaded are returned instead of panicking
+    pub(crate) fn acceptor(self) -> io::Result<Acceptor> {
+        imp::acceptor(self)
+    }
+}
+
+impl From<Identity> for TlsConfig {
+    fn from(identity: Identity) -> Self {
+        TlsConfig::new(identity)
+    }
+}
+
+fn tls_error<E: Display>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Tls setup failed: {}", e))
+}
+
+/// returns the certificate matching the server name, exact names take precedence over wildcards
+fn find_sni<'a, T>(entries: &'a [(String, T)], server_name: &str) -> Option<&'a T> {
+    let server_name = server_name.to_lowercase();
+    let exact = entries.iter().find(|&&(ref name, _)| *name == server_name);
+    let wildcard = || entries.iter().find(|&&(ref name, _)| {
+        name.starts_with("*.") && server_name.find('.').map(|i| &server_name[i..] == &name[1..]).unwrap_or(false)
+    });
+    exact.or_else(wildcard).map(|&(_, ref value)| value)
+}
+
+/// attributes of a certificate subject in the order they are rendered
+const SUBJECT_ATTRIBUTES: [&str; 6] = ["CN", "OU", "O", "L", "ST", "C"];
+
+/// renders subject attributes like ```CN=client,O=Example```, independent of the tls backend
+fn format_subject(mut attributes: Vec<(&'static str, String)>) -> String {
+    attributes.sort_by_key(|&(label, _)| SUBJECT_ATTRIBUTES.iter().position(|l| *l == label));
+    attributes.iter().map(|&(label, ref value)| format!("{}={}", label, value)).collect::<Vec<_>>().join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sni_lookup() {
+        let entries = vec![("*.example.com".to_string(), 1), ("api.example.com".to_string(), 2)];
+        assert_eq!(Some(&2), find_sni(&entries, "API.example.com"));
+        assert_eq!(Some(&1), find_sni(&entries, "www.example.com"));
+        assert_eq!(None, find_sni(&entries, "example.com"));
+        assert_eq!(None, find_sni(&entries, "a.b.example.org"));
+    }
+
+    #[test]
+    fn subject_order() {
+        let attributes = vec![("C", "DE".to_string()), ("O", "Example".to_string()), ("CN", "client".to_string())];
+        assert_eq!("CN=client,O=Example,C=DE", format_subject(attributes));
+    }
+
+    #[test]
+    fn invalid_pem() {
+        assert!(Identity::from_pem(b"no certificate", b"no key").is_err());
+    }
+}
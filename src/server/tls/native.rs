@@ -0,0 +1,213 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! ```native-tls``` backend, openssl on linux, schannel on windows and security framework on macos.
+
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use futures::{Future, Poll};
+use native_tls::{Pkcs12, TlsAcceptor};
+use tokio_tls::{AcceptAsync, TlsAcceptorExt};
+
+use super::TlsConfig;
+use super::super::codec::PeerAddr;
+use super::super::unix::PeerCredentials;
+
+pub type Acceptor = TlsAcceptor;
+pub type Stream<S> = ::tokio_tls::TlsStream<S>;
+
+pub enum Identity {
+    Pkcs12(Pkcs12),
+    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+    Pem(ossl::PemIdentity),
+}
+
+impl From<Pkcs12> for super::Identity {
+    fn from(pkcs: Pkcs12) -> Self {
+        super::Identity { inner: Identity::Pkcs12(pkcs) }
+    }
+}
+
+impl From<Pkcs12> for TlsConfig {
+    fn from(pkcs: Pkcs12) -> Self {
+        TlsConfig::new(pkcs)
+    }
+}
+
+/// tls handshake, resolves to the encrypted stream
+pub struct Handshake<S>(AcceptAsync<S>);
+
+pub fn accept<S: Read + Write>(acceptor: &Acceptor, io: S) -> Handshake<S> {
+    Handshake(acceptor.accept_async(io))
+}
+
+impl<S: Read + Write> Future for Handshake<S> {
+    type Item = Stream<S>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Stream<S>, io::Error> {
+        self.0.poll().map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+    }
+}
+
+impl<S: PeerAddr> PeerAddr for Stream<S> {
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        self.get_ref().get_ref().peer_addr()
+    }
+
+    fn peer_credentials(&self) -> Option<PeerCredentials> {
+        self.get_ref().get_ref().peer_credentials()
+    }
+
+    fn peer_certificate(&self) -> Option<String> {
+        peer_subject(self.get_ref())
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+pub use self::ossl::{from_pem, acceptor, peer_subject};
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+pub use self::other::{from_pem, acceptor, peer_subject};
+
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "ios")))]
+mod ossl {
+    use std::io;
+    use std::sync::Arc;
+    use native_tls::{TlsAcceptor, TlsAcceptorBuilder, TlsStream};
+    use native_tls::backend::openssl::{TlsAcceptorBuilderExt, TlsStreamExt};
+    use openssl::nid;
+    use openssl::pkey::PKey;
+    use openssl::ssl::{SslAcceptorBuilder, SslContext, SslContextBuilder, SslMethod, SslRef,
+                       SSL_VERIFY_PEER, SSL_VERIFY_FAIL_IF_NO_PEER_CERT};
+    use openssl::x509::{X509, X509Name};
+
+    use super::Identity;
+    use super::super::{ClientAuth, TlsConfig, find_sni, format_subject, tls_error};
+
+    pub struct PemIdentity {
+        key: PKey,
+        cert: X509,
+        chain: Vec<X509>,
+    }
+
+    pub fn from_pem(cert_chain: &[u8], key: &[u8]) -> io::Result<Identity> {
+        let mut chain = X509::stack_from_pem(cert_chain).map_err(tls_error)?;
+        if chain.is_empty() {
+            return Err(tls_error("No certificate found"));
+        }
+        let cert = chain.remove(0);
+        let key = PKey::private_key_from_pem(key).map_err(tls_error)?;
+        Ok(Identity::Pem(PemIdentity { key, cert, chain }))
+    }
+
+    pub fn acceptor(config: TlsConfig) -> io::Result<TlsAcceptor> {
+        let TlsConfig { identity, sni, client_auth } = config;
+        let mut builder = match identity.inner {
+            Identity::Pkcs12(pkcs) => TlsAcceptor::builder(pkcs).map_err(tls_error)?,
+            Identity::Pem(pem) => {
+                let ssl = SslAcceptorBuilder::mozilla_intermediate(SslMethod::tls(), &pem.key, &pem.cert, &pem.chain).map_err(tls_error)?;
+                TlsAcceptorBuilder::from_openssl(ssl)
+            }
+        };
+        {
+            let ssl = builder.builder_mut();
+            set_client_auth(ssl, &client_auth)?;
+            if !sni.is_empty() {
+                let mut contexts = Vec::new();
+                for (name, identity) in sni {
+                    contexts.push((name, sni_context(identity.inner, &client_auth)?));
+                }
+                let contexts = Arc::new(contexts);
+                ssl.set_servername_callback(move |ssl: &mut SslRef| {
+                    let context = ssl.servername().and_then(|name| find_sni(&contexts, name));
+                    if let Some(context) = context {
+                        if let Err(e) = ssl.set_ssl_context(context) {
+                            warn!("Could not switch to sni certificate: {}", e);
+                        }
+                    }
+                    Ok(())
+                });
+            }
+        }
+        builder.build().map_err(tls_error)
+    }
+
+    /// openssl only switches the certificate and the ca store when selecting a sni context,
+    /// the verification mode of the default context stays active
+    fn sni_context(identity: Identity, client_auth: &ClientAuth) -> io::Result<SslContext> {
+        let pem = match identity {
+            Identity::Pem(pem) => pem,
+            Identity::Pkcs12(_) => return Err(tls_error("Sni certificates have to be loaded from pem")),
+        };
+        let mut ctx = SslContext::builder(SslMethod::tls()).map_err(tls_error)?;
+        ctx.set_private_key(&pem.key).map_err(tls_error)?;
+        ctx.set_certificate(&pem.cert).map_err(tls_error)?;
+        ctx.check_private_key().map_err(tls_error)?;
+        for cert in pem.chain {
+            ctx.add_extra_chain_cert(cert).map_err(tls_error)?;
+        }
+        set_client_auth(&mut ctx, client_auth)?;
+        Ok(ctx.build())
+    }
+
+    fn set_client_auth(ctx: &mut SslContextBuilder, client_auth: &ClientAuth) -> io::Result<()> {
+        let (ca, mode) = match *client_auth {
+            ClientAuth::None => return Ok(()),
+            ClientAuth::Optional(ref ca) => (ca, SSL_VERIFY_PEER),
+            ClientAuth::Required(ref ca) => (ca, SSL_VERIFY_PEER | SSL_VERIFY_FAIL_IF_NO_PEER_CERT),
+        };
+        ctx.set_ca_file(ca).map_err(tls_error)?;
+        ctx.set_client_ca_list(X509Name::load_client_ca_file(ca).map_err(tls_error)?);
+        // required for session resumption with client certificates
+        ctx.set_session_id_context(b"rest_in_rust").map_err(tls_error)?;
+        ctx.set_verify(mode);
+        Ok(())
+    }
+
+    /// returns the subject of the verified client certificate
+    pub fn peer_subject<S>(stream: &TlsStream<S>) -> Option<String> {
+        let cert = stream.raw_stream().ssl().peer_certificate()?;
+        let names = [(nid::COMMONNAME, "CN"), (nid::ORGANIZATIONALUNITNAME, "OU"), (nid::ORGANIZATIONNAME, "O"),
+            (nid::LOCALITYNAME, "L"), (nid::STATEORPROVINCENAME, "ST"), (nid::COUNTRYNAME, "C")];
+        let mut attributes = Vec::new();
+        for &(nid, label) in names.iter() {
+            for entry in cert.subject_name().entries_by_nid(nid) {
+                if let Ok(value) = entry.data().as_utf8() {
+                    attributes.push((label, value.to_string()));
+                }
+            }
+        }
+        Some(format_subject(attributes))
+    }
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows", target_os = "ios"))]
+mod other {
+    use std::io;
+    use native_tls::{TlsAcceptor, TlsStream};
+
+    use super::Identity;
+    use super::super::{ClientAuth, TlsConfig, tls_error};
+
+    pub fn from_pem(_: &[u8], _: &[u8]) -> io::Result<Identity> {
+        Err(tls_error("Pem certificates are only supported with openssl"))
+    }
+
+    pub fn acceptor(config: TlsConfig) -> io::Result<TlsAcceptor> {
+        if !config.sni.is_empty() || config.client_auth != ClientAuth::None {
+            return Err(tls_error("Sni and client certificates are only supported with openssl"));
+        }
+        let Identity::Pkcs12(pkcs) = config.identity.inner;
+        TlsAcceptor::builder(pkcs).and_then(|b| b.build()).map_err(tls_error)
+    }
+
+    pub fn peer_subject<S>(_: &TlsStream<S>) -> Option<String> {
+        None
+    }
+}
@@ -0,0 +1,210 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Pure rust ```rustls``` backend, no openssl required.
+
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use ::rustls::{AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient, Certificate, NoClientAuth,
+               PrivateKey, ResolvesServerCert, RootCertStore, ServerConfig, ServerSession, Session, SignatureScheme};
+use ::rustls::internal::pemfile;
+use ::rustls::sign::{CertifiedKey, RSASigningKey};
+use ::tokio_io::{AsyncRead, AsyncWrite};
+use ::tokio_rustls::{AcceptAsync, ServerConfigExt};
+use ::webpki::DNSNameRef;
+
+use super::{ClientAuth, TlsConfig, find_sni, format_subject, tls_error};
+use super::super::codec::PeerAddr;
+use super::super::unix::PeerCredentials;
+
+pub type Acceptor = Arc<ServerConfig>;
+pub type Stream<S> = ::tokio_rustls::TlsStream<S, ServerSession>;
+pub type Handshake<S> = AcceptAsync<S>;
+
+pub struct Identity {
+    certs: Vec<Certificate>,
+    key: PrivateKey,
+}
+
+pub fn accept<S: AsyncRead + AsyncWrite>(acceptor: &Acceptor, io: S) -> Handshake<S> {
+    acceptor.accept_async(io)
+}
+
+pub fn from_pem(cert_chain: &[u8], key: &[u8]) -> io::Result<Identity> {
+    let certs = pemfile::certs(&mut &cert_chain[..]).map_err(|_| tls_error("Invalid certificate"))?;
+    if certs.is_empty() {
+        return Err(tls_error("No certificate found"));
+    }
+    let mut keys = pemfile::pkcs8_private_keys(&mut &key[..]).map_err(|_| tls_error("Invalid private key"))?;
+    if keys.is_empty() {
+        keys = pemfile::rsa_private_keys(&mut &key[..]).map_err(|_| tls_error("Invalid private key"))?;
+    }
+    if keys.is_empty() {
+        return Err(tls_error("No private key found"));
+    }
+    Ok(Identity { certs, key: keys.remove(0) })
+}
+
+pub fn acceptor(config: TlsConfig) -> io::Result<Acceptor> {
+    let TlsConfig { identity, sni, client_auth } = config;
+    let verifier = match client_auth {
+        ClientAuth::None => NoClientAuth::new(),
+        ClientAuth::Optional(ref ca) => AllowAnyAnonymousOrAuthenticatedClient::new(roots(ca)?),
+        ClientAuth::Required(ref ca) => AllowAnyAuthenticatedClient::new(roots(ca)?),
+    };
+    let mut names = Vec::new();
+    for (name, identity) in sni {
+        names.push((name, certified_key(identity.inner)?));
+    }
+    let mut config = ServerConfig::new(verifier);
+    config.cert_resolver = Arc::new(SniResolver { default: certified_key(identity.inner)?, names });
+    Ok(Arc::new(config))
+}
+
+fn certified_key(identity: Identity) -> io::Result<CertifiedKey> {
+    let key = RSASigningKey::new(&identity.key).map_err(|_| tls_error("Unsupported private key, only rsa keys can be used"))?;
+    Ok(CertifiedKey::new(identity.certs, Arc::new(Box::new(key))))
+}
+
+fn roots(ca: &Path) -> io::Result<RootCertStore> {
+    let pem = fs::read(ca)?;
+    let mut roots = RootCertStore::empty();
+    roots.add_pem_file(&mut &pem[..]).map_err(|_| tls_error("Invalid ca bundle"))?;
+    if roots.is_empty() {
+        return Err(tls_error("No ca certificate found"));
+    }
+    Ok(roots)
+}
+
+/// selects the certificate by the requested server name
+struct SniResolver {
+    default: CertifiedKey,
+    names: Vec<(String, CertifiedKey)>,
+}
+
+impl ResolvesServerCert for SniResolver {
+    fn resolve(&self, server_name: Option<DNSNameRef>, _: &[SignatureScheme]) -> Option<CertifiedKey> {
+        let key = server_name.and_then(|name| find_sni(&self.names, name.into()));
+        Some(key.unwrap_or(&self.default).clone())
+    }
+}
+
+impl<S: PeerAddr> PeerAddr for Stream<S> {
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        self.get_ref().0.peer_addr()
+    }
+
+    fn peer_credentials(&self) -> Option<PeerCredentials> {
+        self.get_ref().0.peer_credentials()
+    }
+
+    fn peer_certificate(&self) -> Option<String> {
+        let certs = self.get_ref().1.get_peer_certificates()?;
+        certs.first().and_then(|cert| subject(&cert.0))
+    }
+}
+
+/// reads the subject of a der encoded x509 certificate, rustls only verifies certificates but does not expose their content
+fn subject(der: &[u8]) -> Option<String> {
+    let (_, certificate, _) = read_der(der)?;
+    let (_, tbs, _) = read_der(certificate)?;
+    let (tag, _, after_version) = read_der(tbs)?;
+    // the version is optional
+    let mut rest = if tag == 0xa0 { after_version } else { tbs };
+    // serial number, signature algorithm, issuer and validity precede the subject
+    for _ in 0..4 {
+        rest = read_der(rest)?.2;
+    }
+    let (_, mut names, _) = read_der(rest)?;
+    let mut attributes = Vec::new();
+    while !names.is_empty() {
+        let (_, mut set, next) = read_der(names)?;
+        names = next;
+        while !set.is_empty() {
+            let (_, attribute, next) = read_der(set)?;
+            set = next;
+            let (_, oid, value) = read_der(attribute)?;
+            let (_, value, _) = read_der(value)?;
+            if let Some(label) = subject_label(oid) {
+                attributes.push((label, String::from_utf8_lossy(value).into_owned()));
+            }
+        }
+    }
+    Some(format_subject(attributes))
+}
+
+fn subject_label(oid: &[u8]) -> Option<&'static str> {
+    match *oid {
+        [0x55, 0x04, 0x03] => Some("CN"),
+        [0x55, 0x04, 0x0b] => Some("OU"),
+        [0x55, 0x04, 0x0a] => Some("O"),
+        [0x55, 0x04, 0x07] => Some("L"),
+        [0x55, 0x04, 0x08] => Some("ST"),
+        [0x55, 0x04, 0x06] => Some("C"),
+        _ => None,
+    }
+}
+
+/// splits a der element into tag, content and the remaining input
+fn read_der(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    if data.len() < 2 {
+        return None;
+    }
+    let (length, header) = if data[1] < 0x80 {
+        (data[1] as usize, 2)
+    } else {
+        let bytes = (data[1] & 0x7f) as usize;
+        if bytes == 0 || bytes > 4 || data.len() < 2 + bytes {
+            return None;
+        }
+        (data[2..2 + bytes].iter().fold(0, |length, b| length << 8 | *b as usize), 2 + bytes)
+    };
+    if data.len() < header + length {
+        return None;
+    }
+    Some((data[0], &data[header..header + length], &data[header + length..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut result = vec![tag, content.len() as u8];
+        result.extend_from_slice(content);
+        result
+    }
+
+    fn attribute(oid: u8, value: &str) -> Vec<u8> {
+        let mut content = der(0x06, &[0x55, 0x04, oid]);
+        content.extend(der(0x0c, value.as_bytes()));
+        der(0x31, &der(0x30, &content))
+    }
+
+    #[test]
+    fn certificate_subject() {
+        let mut name = attribute(0x06, "DE");
+        name.extend(attribute(0x0a, "Example"));
+        name.extend(attribute(0x03, "client"));
+
+        let mut tbs = der(0xa0, &der(0x02, &[2]));
+        tbs.extend(der(0x02, &[1]));
+        tbs.extend(der(0x30, &[]));
+        tbs.extend(der(0x30, &attribute(0x03, "issuer")));
+        tbs.extend(der(0x30, &[]));
+        tbs.extend(der(0x30, &name));
+        let certificate = der(0x30, &der(0x30, &tbs));
+
+        assert_eq!(Some("CN=client,O=Example,C=DE".to_string()), subject(&certificate));
+        assert_eq!(None, subject(&certificate[..10]));
+    }
+}
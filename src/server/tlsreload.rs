@@ -0,0 +1,299 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Tls certificates which can be replaced while the server is running.
+//!
+//! New handshakes use the reloaded certificates, established connections are not affected.
+//! A reload is triggered by ```ReloadableTls::reload```, by a changed file or by SIGHUP (unix only).
+//! Changed files and signals are handled by background threads, so certificates are never loaded on the event loop.
+//! If loading fails the previous certificates stay active.
+//!
+//! ```rust,no_run
+//! # use rest_in_rust::*;
+//! # use std::time::Duration;
+//! use rest_in_rust::server::{ReloadableTls, TlsConfig, Identity};
+//!
+//! let tls = ReloadableTls::new(|| Ok(TlsConfig::new(Identity::from_pem_files("cert.pem", "key.pem")?))).unwrap();
+//! tls.watch_files(vec!["cert.pem", "key.pem"], Duration::from_secs(60));
+//! #[cfg(unix)]
+//! tls.reload_on_sighup().unwrap();
+//!
+//! let s = Server::new("0.0.0.0:443".parse().unwrap(), Router::new());
+//! s.start_https(tls.clone()).unwrap();
+//! ```
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock, Weak};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use futures::{Async, Future, IntoFuture, Poll};
+use tokio_io::{AsyncRead, AsyncWrite};
+use tokio_proto::pipeline::ServerProto;
+
+use super::tls::{self, Acceptor, Handshake, Identity, Stream, TlsConfig};
+
+/// Tls certificates of https listeners which can be reloaded at runtime
+#[derive(Clone)]
+pub struct ReloadableTls {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    source: Option<Box<Fn() -> io::Result<TlsConfig> + Send + Sync>>,
+    acceptor: RwLock<Arc<Acceptor>>,
+    /// incremented by ```watch_files```, older watch threads stop
+    watch_generation: AtomicUsize,
+}
+
+struct FileWatch {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    interval: Duration,
+    last_check: Instant,
+}
+
+impl ReloadableTls {
+    /// loads the certificates from the source, the source is called again on every reload
+    pub fn new<F>(source: F) -> io::Result<Self>
+        where F: Fn() -> io::Result<TlsConfig> + Send + Sync + 'static {
+        let acceptor = source()?.acceptor()?;
+        Ok(ReloadableTls::create(Some(Box::new(source)), acceptor))
+    }
+
+    /// certificates without a source, they can only be replaced via ```ReloadableTls::set```
+    pub fn fixed(config: TlsConfig) -> io::Result<Self> {
+        Ok(ReloadableTls::create(None, config.acceptor()?))
+    }
+
+    fn create(source: Option<Box<Fn() -> io::Result<TlsConfig> + Send + Sync>>, acceptor: Acceptor) -> Self {
+        let inner = Inner { source, acceptor: RwLock::new(Arc::new(acceptor)), watch_generation: AtomicUsize::new(0) };
+        ReloadableTls { inner: Arc::new(inner) }
+    }
+
+    /// loads the certificates from the source again and uses them for new connections
+    pub fn reload(&self) -> io::Result<()> {
+        let config = match self.inner.source {
+            Some(ref source) => source(),
+            None => Err(io::Error::new(io::ErrorKind::Other, "No certificate source to reload from")),
+        };
+        match config.and_then(|config| self.set(config)) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                error!("Reloading tls certificates failed, keeping the previous ones: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// replaces the certificates used for new connections
+    pub fn set(&self, config: TlsConfig) -> io::Result<()> {
+        let acceptor = config.acceptor()?;
+        *self.inner.acceptor.write().unwrap() = Arc::new(acceptor);
+        info!("Reloaded tls certificates");
+        Ok(())
+    }
+
+    /// reloads when the modification time of one of the files changes.
+    /// A background thread checks the files once per interval, calling it again replaces the watched files
+    pub fn watch_files<P: Into<PathBuf>>(&self, files: Vec<P>, interval: Duration) {
+        let files = files.into_iter().map(|f| {
+            let path = f.into();
+            let modified = modified(&path);
+            (path, modified)
+        }).collect();
+        let mut watch = FileWatch { files, interval, last_check: Instant::now() };
+        let generation = self.inner.watch_generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let tls = Arc::downgrade(&self.inner);
+        let spawned = thread::Builder::new().name("tls-watch".into()).spawn(move || loop {
+            thread::sleep(watch.interval);
+            let tls = match upgrade(&tls) {
+                Some(ref tls) if tls.inner.watch_generation.load(Ordering::SeqCst) != generation => break,
+                Some(tls) => tls,
+                None => break,
+            };
+            if watch.changed() {
+                let _ = tls.reload();
+            }
+        });
+        if let Err(e) = spawned {
+            error!("Could not start watching tls files: {}", e);
+        }
+    }
+
+    /// reloads after the process received SIGHUP, the certificates are loaded by a background thread
+    #[cfg(unix)]
+    pub fn reload_on_sighup(&self) -> io::Result<()> {
+        let signals = ::signal_hook::iterator::Signals::new(&[::signal_hook::SIGHUP])?;
+        let tls = Arc::downgrade(&self.inner);
+        thread::Builder::new().name("tls-sighup".into()).spawn(move || {
+            for _ in signals.forever() {
+                match upgrade(&tls) {
+                    Some(tls) => {
+                        let _ = tls.reload();
+                    }
+                    None => break,
+                }
+            }
+        })?;
+        Ok(())
+    }
+
+    /// the acceptor for a new connection, called on the event loop
+    pub(crate) fn acceptor(&self) -> Arc<Acceptor> {
+        self.inner.acceptor.read().unwrap().clone()
+    }
+}
+
+/// the certificates of a background thread, ```None``` once they are dropped
+fn upgrade(tls: &Weak<Inner>) -> Option<ReloadableTls> {
+    tls.upgrade().map(|inner| ReloadableTls { inner })
+}
+
+impl FileWatch {
+    /// checks the modification times if the interval elapsed since the last check
+    fn changed(&mut self) -> bool {
+        if self.last_check.elapsed() < self.interval {
+            return false;
+        }
+        self.last_check = Instant::now();
+        let mut changed = false;
+        for &mut (ref path, ref mut last_modified) in self.files.iter_mut() {
+            let modified = modified(path);
+            if modified != *last_modified {
+                debug!("Tls file {:?} changed", path);
+                *last_modified = modified;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+fn modified(path: &PathBuf) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Types usable as certificates of https listeners
+pub trait IntoTls {
+    fn into_tls(self) -> io::Result<ReloadableTls>;
+}
+
+impl IntoTls for ReloadableTls {
+    fn into_tls(self) -> io::Result<ReloadableTls> {
+        Ok(self)
+    }
+}
+
+impl IntoTls for TlsConfig {
+    fn into_tls(self) -> io::Result<ReloadableTls> {
+        ReloadableTls::fixed(self)
+    }
+}
+
+impl IntoTls for Identity {
+    fn into_tls(self) -> io::Result<ReloadableTls> {
+        ReloadableTls::fixed(self.into())
+    }
+}
+
+#[cfg(feature = "tls-native")]
+impl IntoTls for ::native_tls::Pkcs12 {
+    fn into_tls(self) -> io::Result<ReloadableTls> {
+        ReloadableTls::fixed(self.into())
+    }
+}
+
+/// Like ```tokio_tls::proto::Server```, but independent of the tls backend and takes the acceptor of every connection from ```ReloadableTls```
+pub struct TlsProto<T> {
+    inner: Arc<T>,
+    tls: ReloadableTls,
+}
+
+impl<T> TlsProto<T> {
+    pub fn new(inner: T, tls: ReloadableTls) -> Self {
+        TlsProto { inner: Arc::new(inner), tls }
+    }
+}
+
+impl<T, I> ServerProto<I> for TlsProto<T>
+    where T: ServerProto<Stream<I>>,
+          I: AsyncRead + AsyncWrite + 'static {
+    type Request = T::Request;
+    type Response = T::Response;
+    type Transport = T::Transport;
+    type BindTransport = TlsBind<T, I>;
+
+    fn bind_transport(&self, io: I) -> TlsBind<T, I> {
+        let acceptor = self.tls.acceptor();
+        let handshake = tls::accept(&acceptor, io);
+        TlsBind { state: BindState::Handshake(handshake, self.inner.clone()) }
+    }
+}
+
+/// handshake followed by binding the inner protocol
+pub struct TlsBind<T, I>
+    where T: ServerProto<Stream<I>>,
+          I: AsyncRead + AsyncWrite + 'static {
+    state: BindState<T, I>,
+}
+
+enum BindState<T, I>
+    where T: ServerProto<Stream<I>>,
+          I: AsyncRead + AsyncWrite + 'static {
+    Handshake(Handshake<I>, Arc<T>),
+    Bind(<T::BindTransport as IntoFuture>::Future),
+}
+
+impl<T, I> Future for TlsBind<T, I>
+    where T: ServerProto<Stream<I>>,
+          I: AsyncRead + AsyncWrite + 'static {
+    type Item = T::Transport;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<T::Transport, io::Error> {
+        loop {
+            let bind = match self.state {
+                BindState::Handshake(ref mut handshake, ref inner) => {
+                    match handshake.poll()? {
+                        Async::Ready(stream) => inner.bind_transport(stream),
+                        Async::NotReady => return Ok(Async::NotReady),
+                    }
+                }
+                BindState::Bind(ref mut bind) => return bind.poll(),
+            };
+            self.state = BindState::Bind(bind.into_future());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn detects_changed_files() {
+        let dir = TempDir::new("tlsreload").unwrap();
+        let path = dir.path().join("cert.pem");
+        fs::write(&path, "first").unwrap();
+
+        let mut watch = FileWatch { files: vec![(path.clone(), modified(&path))], interval: Duration::from_secs(0), last_check: Instant::now() };
+        assert!(!watch.changed());
+
+        fs::remove_file(&path).unwrap();
+        assert!(watch.changed());
+        assert!(!watch.changed());
+
+        watch.interval = Duration::from_secs(3600);
+        fs::write(&path, "second").unwrap();
+        assert!(!watch.changed());
+    }
+}
@@ -0,0 +1,224 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Unix domain socket listener, see ```Server::new_unix```.
+//!
+//! ```rust,no_run
+//! # use rest_in_rust::*;
+//! use rest_in_rust::server::UnixSocket;
+//!
+//! let socket = UnixSocket::new("/run/my-service/http.sock").mode(0o660);
+//! let s = Server::new_unix(socket, Router::new());
+//! s.start_http().unwrap();
+//! ```
+
+#[cfg(unix)]
+use std::io;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+/// Credentials of the process connected via a unix domain socket
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PeerCredentials {
+    pub uid: u32,
+    pub gid: u32,
+    /// only available on linux
+    pub pid: Option<i32>,
+}
+
+/// Configuration of a unix domain socket to listen on
+#[cfg(unix)]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct UnixSocket {
+    path: PathBuf,
+    mode: Option<u32>,
+    remove_stale: bool,
+}
+
+#[cfg(unix)]
+impl UnixSocket {
+    /// listens on the given path, a stale socket file left over by a crashed process is removed.
+    /// The socket file is removed again when the server stops.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        UnixSocket { path: path.into(), mode: None, remove_stale: true }
+    }
+
+    /// sets the file permissions of the socket, eg. ```0o660```
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// defines if a stale socket file is removed before binding.
+    /// A socket file is stale if no process accepts connections on it anymore.
+    pub fn remove_stale(mut self, remove_stale: bool) -> Self {
+        self.remove_stale = remove_stale;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn bind(&self) -> io::Result<UnixListener> {
+        use std::fs;
+        use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+        if let Ok(metadata) = fs::symlink_metadata(&self.path) {
+            let stale = metadata.file_type().is_socket() && is_stale(&self.path);
+            if !self.remove_stale || !stale {
+                return Err(io::Error::new(io::ErrorKind::AddrInUse, format!("{:?} already exists", self.path)));
+            }
+            info!("Removing stale socket {:?}", self.path);
+            fs::remove_file(&self.path)?;
+        }
+
+        let listener = match self.mode {
+            Some(mode) => {
+                // the socket must not be reachable with the default permissions before it is restricted to mode
+                let listener = {
+                    let _umask = Umask::set(0o177);
+                    UnixListener::bind(&self.path)?
+                };
+                fs::set_permissions(&self.path, fs::Permissions::from_mode(mode))?;
+                listener
+            }
+            None => UnixListener::bind(&self.path)?,
+        };
+        Ok(listener)
+    }
+
+    pub(crate) fn cleanup(&self) {
+        if let Err(e) = ::std::fs::remove_file(&self.path) {
+            warn!("Could not remove socket {:?}: {}", self.path, e);
+        }
+    }
+}
+
+/// Replaces the umask of the process until dropped.
+/// The umask is process wide, so binds with a mode are serialized
+#[cfg(unix)]
+struct Umask {
+    previous: ::libc::mode_t,
+}
+
+#[cfg(unix)]
+static UMASK_LOCKED: ::std::sync::atomic::AtomicBool = ::std::sync::atomic::ATOMIC_BOOL_INIT;
+
+#[cfg(unix)]
+impl Umask {
+    fn set(mask: ::libc::mode_t) -> Self {
+        use std::sync::atomic::Ordering;
+        while UMASK_LOCKED.compare_and_swap(false, true, Ordering::Acquire) {
+            ::std::thread::yield_now();
+        }
+        let previous = unsafe { ::libc::umask(mask) };
+        Umask { previous }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Umask {
+    fn drop(&mut self) {
+        unsafe { ::libc::umask(self.previous) };
+        UMASK_LOCKED.store(false, ::std::sync::atomic::Ordering::Release);
+    }
+}
+
+#[cfg(unix)]
+fn is_stale(path: &Path) -> bool {
+    match UnixStream::connect(path) {
+        Ok(_) => false,
+        Err(e) => e.kind() == io::ErrorKind::ConnectionRefused,
+    }
+}
+
+/// returns the address family of the socket, eg. ```AF_UNIX```
+#[cfg(unix)]
+pub(crate) fn socket_family(fd: ::std::os::unix::io::RawFd) -> io::Result<::libc::c_int> {
+    let mut addr: ::libc::sockaddr_storage = unsafe { ::std::mem::zeroed() };
+    let mut len = ::std::mem::size_of::<::libc::sockaddr_storage>() as ::libc::socklen_t;
+    let ret = unsafe { ::libc::getsockname(fd, &mut addr as *mut ::libc::sockaddr_storage as *mut ::libc::sockaddr, &mut len) };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(addr.ss_family as ::libc::c_int)
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn peer_credentials(stream: &::tokio_uds::UnixStream) -> Option<PeerCredentials> {
+    use std::mem;
+    use std::os::unix::io::AsRawFd;
+    use libc::{getsockopt, socklen_t, ucred, c_void, SOL_SOCKET, SO_PEERCRED};
+
+    let mut cred = ucred { pid: 0, uid: 0, gid: 0 };
+    let mut len = mem::size_of::<ucred>() as socklen_t;
+    let ret = unsafe {
+        getsockopt(stream.as_raw_fd(), SOL_SOCKET, SO_PEERCRED, &mut cred as *mut ucred as *mut c_void, &mut len)
+    };
+    if ret == 0 && len as usize == mem::size_of::<ucred>() {
+        Some(PeerCredentials { uid: cred.uid, gid: cred.gid, pid: Some(cred.pid) })
+    } else {
+        None
+    }
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+pub(crate) fn peer_credentials(stream: &::tokio_uds::UnixStream) -> Option<PeerCredentials> {
+    stream.peer_cred().ok().map(|c| PeerCredentials { uid: c.uid as u32, gid: c.gid as u32, pid: None })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn stale_socket_is_removed() {
+        let dir = TempDir::new("unixsocket").unwrap();
+        let path = dir.path().join("http.sock");
+        let socket = UnixSocket::new(path.clone()).mode(0o600);
+
+        drop(socket.bind().unwrap());
+        assert!(path.exists());
+
+        let listener = socket.bind().unwrap();
+        assert!(socket.bind().is_err());
+        assert!(UnixSocket::new(path.clone()).remove_stale(false).bind().is_err());
+        drop(listener);
+
+        socket.cleanup();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn mode_is_applied() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new("unixsocket").unwrap();
+        let path = dir.path().join("http.sock");
+        // reading the umask replaces it, so it's read while holding the lock of Umask
+        let current_umask = || Umask::set(0o022).previous;
+        let before = current_umask();
+
+        let _listener = UnixSocket::new(path.clone()).mode(0o640).bind().unwrap();
+        assert_eq!(0o640, ::std::fs::metadata(&path).unwrap().permissions().mode() & 0o777);
+        assert_eq!(before, current_umask());
+    }
+
+    #[test]
+    fn refuses_regular_files() {
+        let dir = TempDir::new("unixsocket").unwrap();
+        let path = dir.path().join("file");
+        ::std::fs::File::create(&path).unwrap();
+        assert!(UnixSocket::new(path).bind().is_err());
+    }
+}
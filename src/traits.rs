@@ -55,7 +55,7 @@ pub trait FromRequest: Sized {
 ///         let state: Option<&MyState> = req.get_state();
 ///         match state {
 ///            Some(state) => Ok(state),
-///            None => Err("No global state present".into())
+///            None => Err(HttpError::internal_server_error("No global state present"))
 ///         }
 ///     }
 /// }
@@ -0,0 +1,224 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Validation of deserialized input.
+//!
+//! Invalid fields are collected in ```ValidationErrors``` and answered with 422 unprocessable entity,
+//! listing every field in the message and as ```invalid_fields``` member of the problem details.
+//! Deserialization errors of a single field, like a wrong type or a missing field, are reported the same way.
+//!
+//! ```
+//! # extern crate rest_in_rust;
+//! # extern crate regex;
+//! # #[macro_use] extern crate serde_derive;
+//! use rest_in_rust::*;
+//! use rest_in_rust::validate::{Validate, ValidationErrors};
+//!
+//! #[derive(Deserialize)]
+//! struct User {
+//!     name: String,
+//!     age: u32,
+//!     company: Option<String>,
+//!     business: bool,
+//! }
+//!
+//! impl Validate for User {
+//!     fn validate(&self) -> Result<(), ValidationErrors> {
+//!         let mut errors = ValidationErrors::new();
+//!         errors.length("name", &self.name, 1, 64)
+//!             .matches("name", &self.name, &regex::Regex::new("^[a-z]+$").unwrap())
+//!             .range("age", self.age, 18, 150)
+//!             .required_if("company", self.company.is_some(), self.business);
+//!         errors.into_result()
+//!     }
+//! }
+//!
+//! fn create_user(req: &mut Request) -> Result<Response, HttpError> {
+//!     let user: User = req.valid_body()?;
+//!     Ok(format!("Created {}", user.name).into())
+//! }
+//!
+//! # fn main() {
+//! let mut r = Router::new();
+//! r.post("/users", create_user);
+//! # }
+//! ```
+
+use std::fmt::Display;
+use regex::Regex;
+
+use error::HttpError;
+
+/// Semantic checks of deserialized input
+pub trait Validate {
+    fn validate(&self) -> Result<(), ValidationErrors>;
+}
+
+/// A single invalid field, the field is a path like ```address.street``` or ```tags[1]```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// All invalid fields of an input, converts to a 422 ```HttpError```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationErrors {
+    errors: Vec<FieldError>,
+}
+
+impl ValidationErrors {
+    pub fn new() -> Self {
+        ValidationErrors { errors: Vec::new() }
+    }
+
+    pub fn add<F: Into<String>, M: Into<String>>(&mut self, field: F, message: M) -> &mut Self {
+        self.errors.push(FieldError { field: field.into(), message: message.into() });
+        self
+    }
+
+    /// adds the errors of a nested value, their fields are prefixed with ```field.```
+    pub fn nested<F: AsRef<str>>(&mut self, field: F, result: Result<(), ValidationErrors>) -> &mut Self {
+        if let Err(nested) = result {
+            for error in nested.errors {
+                let field = format!("{}.{}", field.as_ref(), error.field);
+                self.add(field, error.message);
+            }
+        }
+        self
+    }
+
+    /// value has to be within ```min``` and ```max```, both inclusive
+    pub fn range<F: Into<String>, T: PartialOrd + Display>(&mut self, field: F, value: T, min: T, max: T) -> &mut Self {
+        if value < min || value > max {
+            self.add(field, format!("must be between {} and {}", min, max));
+        }
+        self
+    }
+
+    /// number of characters has to be within ```min``` and ```max```, both inclusive
+    pub fn length<F: Into<String>>(&mut self, field: F, value: &str, min: usize, max: usize) -> &mut Self {
+        let length = value.chars().count();
+        if length < min || length > max {
+            self.add(field, format!("length must be between {} and {}", min, max));
+        }
+        self
+    }
+
+    pub fn matches<F: Into<String>>(&mut self, field: F, value: &str, regex: &Regex) -> &mut Self {
+        if !regex.is_match(value) {
+            self.add(field, format!("must match {}", regex.as_str()));
+        }
+        self
+    }
+
+    /// the field has to be present if the condition holds
+    pub fn required_if<F: Into<String>>(&mut self, field: F, present: bool, condition: bool) -> &mut Self {
+        if condition && !present {
+            self.add(field, "is required");
+        }
+        self
+    }
+
+    pub fn errors(&self) -> &[FieldError] {
+        &self.errors
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// ```Ok``` if no field is invalid
+    pub fn into_result(self) -> Result<(), ValidationErrors> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl From<ValidationErrors> for HttpError {
+    fn from(errors: ValidationErrors) -> Self {
+        let fields = errors.errors.iter().map(|e| format!("{}: {}", e.field, e.message)).collect::<Vec<_>>().join("\n");
+        HttpError::unprocessable_entity(format!("Invalid input\n{}", fields))
+            .extension("invalid_fields", &errors.errors)
+    }
+}
+
+/// a malformed body, answered with 400 keeping the position reported by the deserializer
+pub(crate) fn syntax_error<E: Display>(media_type: &str, e: E) -> HttpError {
+    debug!("Could not parse body as {}: {}", media_type, e);
+    HttpError::bad_request(format!("Could not parse body as {}: {}", media_type, e))
+}
+
+/// a well formed body not matching the expected type, answered with 422 for the field at ```path``` (```.``` is the root)
+pub(crate) fn data_error<E: Display>(media_type: &str, path: &str, e: E) -> HttpError {
+    let message = e.to_string();
+    debug!("Invalid {} body at {}: {}", media_type, path, message);
+    let missing = if message.starts_with("missing field `") {
+        message["missing field `".len()..].split('`').next()
+    } else {
+        None
+    };
+    let field = match (path, missing) {
+        (".", Some(missing)) => missing.to_string(),
+        (path, Some(missing)) => format!("{}.{}", path, missing),
+        (path, None) => path.to_string(),
+    };
+    let mut errors = ValidationErrors::new();
+    errors.add(field, message);
+    errors.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collect_errors() {
+        let mut address = ValidationErrors::new();
+        address.length("street", "", 1, 10);
+        let mut errors = ValidationErrors::new();
+        errors.range("age", 12, 18, 150)
+            .range("height", 180, 50, 250)
+            .length("name", "jürgen", 1, 6)
+            .matches("name", "Admin", &Regex::new("^[a-z]+$").unwrap())
+            .required_if("company", false, true)
+            .required_if("vat", false, false)
+            .nested("address", address.into_result());
+        let fields: Vec<&str> = errors.errors().iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(vec!["age", "name", "company", "address.street"], fields);
+
+        let err: HttpError = errors.into();
+        assert_eq!(422, err.status.as_u16());
+        assert!(err.msg.contains("age: must be between 18 and 150"));
+        let problem = ::problem::ProblemDetails::to_json(&err);
+        assert_eq!("address.street", problem["invalid_fields"][3]["field"]);
+
+        assert_eq!(Ok(()), ValidationErrors::new().into_result());
+    }
+
+    #[test]
+    fn deserialize_errors() {
+        let err = syntax_error("application/json", "EOF while parsing a value at line 1 column 9");
+        assert_eq!(400, err.status.as_u16());
+        assert!(err.msg.contains("line 1 column 9"));
+
+        let err = data_error("application/json", ".", "missing field `name` at line 1 column 2");
+        assert_eq!(422, err.status.as_u16());
+        assert!(err.msg.contains("name: missing field"));
+
+        let err = data_error("application/json", "address", "missing field `street` at line 1 column 16");
+        assert!(err.msg.contains("address.street: missing field"));
+
+        let err = data_error("application/json", "tags[1]", "invalid type: integer `1`, expected a string");
+        assert!(err.msg.contains("tags[1]: invalid type"));
+    }
+}
@@ -0,0 +1,56 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+extern crate rest_in_rust;
+
+use rest_in_rust::*;
+use rest_in_rust::server::{HttpsRedirect, ListenAddr};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+
+fn hello(_: &mut Request) -> Result<Response, HttpError> {
+    Ok("hello".into())
+}
+
+fn send(addr: SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: example.com:{}\r\n\r\n", path, addr.port()).unwrap();
+    let mut buf = [0u8; 1024];
+    let read = stream.read(&mut buf).unwrap();
+    String::from_utf8_lossy(&buf[..read]).to_string()
+}
+
+#[test]
+fn serve_multiple_listeners() {
+    let mut r = Router::new();
+    r.get("/hello", hello);
+    let mut s = Server::new("127.0.0.1:0".parse().unwrap(), r);
+    s.add_http_listener("127.0.0.1:0".parse().unwrap());
+    s.add_redirect_listener("127.0.0.1:0".parse().unwrap(), HttpsRedirect::new(8443));
+    let handle = s.start_http_non_blocking().unwrap();
+    handle.wait_ready().unwrap();
+
+    let addrs: Vec<SocketAddr> = handle.listen_addrs().iter().map(|addr| match *addr {
+        ListenAddr::Tcp(addr) => addr,
+        _ => panic!("Expected tcp listener"),
+    }).collect();
+    assert_eq!(3, addrs.len());
+    assert_eq!(handle.local_addr(), Some(addrs[0]));
+
+    for addr in &addrs[..2] {
+        let response = send(*addr, "/hello");
+        assert!(response.starts_with("HTTP/1.1 200"), response);
+    }
+
+    let response = send(addrs[2], "/hello?a=b");
+    assert!(response.starts_with("HTTP/1.1 308"), response);
+    assert!(response.to_lowercase().contains("location: https://example.com:8443/hello?a=b"), response);
+
+    handle.stop();
+    handle.join().unwrap();
+}
@@ -11,7 +11,7 @@ extern crate reqwest;
 extern crate http;
 
 use rest_in_rust::*;
-use rest_in_rust::server::ServerStopper;
+use rest_in_rust::server::{ServerStopper, ServerHandle};
 use std::sync::RwLock;
 
 struct State {
@@ -59,45 +59,56 @@ fn configure() -> Router {
     r
 }
 
-fn setup() -> ServerStopper {
-    let addr = "127.0.0.1:8091".parse().unwrap();
+fn setup() -> ServerHandle {
+    let addr = "127.0.0.1:0".parse().unwrap();
     let state = State::default();
     let r = configure();
     let s = Server::new(addr, r);
     s.add_state(state);
-    let stopper = s.start_http_non_blocking().unwrap();
-    stopper
+    let handle = s.start_http_non_blocking().unwrap();
+    handle.wait_ready().unwrap();
+    handle
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::io::Read;
+    use std::net::SocketAddr;
 
     #[test]
     fn state_req() {
-        super::setup();
-        //        let stopper = super::setup();
+        let handle = super::setup();
+        let addr = handle.local_addr().unwrap();
+        assert_ne!(0, addr.port());
 
-        let answer = get("hallo");
+        let answer = get(addr, "hallo");
         assert_eq!("hallo", answer.as_str());
 
-        get("sauerland");
+        get(addr, "sauerland");
 
 
-        let answer = get("history");
+        let answer = get(addr, "history");
         assert_eq!("hallo\nsauerland", answer.as_str());
 
-        //shutdown does not work
-        //        get("shutdown");
-        //        ::std::thread::sleep_ms(5000);
-        //        let result= ::reqwest::get("http://127.0.0.1:8091/");
-        //        println!("{:?}",result);
-        //        assert!(result.is_err());
+        handle.stop();
+        handle.join().unwrap();
     }
 
-    fn get(path: &str) -> String {
-        let url = format!("http://127.0.0.1:8091/{}", path);
+    #[test]
+    fn shutdown_from_handler() {
+        let handle = super::setup();
+        let addr = handle.local_addr().unwrap();
+
+        assert_eq!("Shutting down", get(addr, "shutdown").as_str());
+        handle.join().unwrap();
+
+        let result = ::reqwest::get(format!("http://{}/hallo", addr).as_str());
+        assert!(result.is_err());
+    }
+
+    fn get(addr: SocketAddr, path: &str) -> String {
+        let url = format!("http://{}/{}", addr, path);
         let mut response = ::reqwest::get(url.as_str()).unwrap();
 
         let mut answer = String::new();
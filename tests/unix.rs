@@ -0,0 +1,49 @@
+// Copyright 2017 Christian Löhnert. See the COPYRIGHT
+// file at the top-level directory of this distribution.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+#![cfg(unix)]
+extern crate rest_in_rust;
+extern crate tempdir;
+
+use rest_in_rust::*;
+use rest_in_rust::server::UnixSocket;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use tempdir::TempDir;
+
+fn whoami(req: &mut Request) -> Result<Response, HttpError> {
+    let credentials = req.peer_credentials().ok_or(HttpError::unauthorized("No peer credentials"))?;
+    assert!(req.remote_addr().is_none());
+    Ok(format!("uid={}", credentials.uid).into())
+}
+
+#[test]
+fn serve_unix_socket() {
+    let dir = TempDir::new("unixserver").unwrap();
+    let path = dir.path().join("http.sock");
+
+    let mut r = Router::new();
+    r.get("/whoami", whoami);
+    let s = Server::new_unix(UnixSocket::new(path.clone()).mode(0o600), r);
+    let handle = s.start_http_non_blocking().unwrap();
+    handle.wait_ready().unwrap();
+    assert!(handle.local_addr().is_none());
+
+    let mut stream = UnixStream::connect(&path).unwrap();
+    stream.write_all(b"GET /whoami HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let mut buf = [0u8; 1024];
+    let read = stream.read(&mut buf).unwrap();
+    let response = String::from_utf8_lossy(&buf[..read]).to_string();
+    assert!(response.starts_with("HTTP/1.1 200"), response);
+    assert!(response.contains("uid="), response);
+    drop(stream);
+
+    handle.stop();
+    handle.join().unwrap();
+    assert!(!path.exists());
+}